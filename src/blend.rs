@@ -0,0 +1,88 @@
+use crate::pixels::Depth;
+
+/// How a source color is combined with the color already on the canvas
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum BlendMode{
+    #[default]
+    Normal,
+    Multiply,
+    Screen,
+    Overlay,
+    Add,
+    Darken,
+    Lighten,
+    Difference,
+    /// Writes the source as it is, alpha included
+    Replace
+}
+
+impl BlendMode{
+    pub const NAMES:&'static [&'static str] = &[
+        "normal", "multiply", "screen", "overlay", "add", "darken", "lighten", "difference", "replace"
+    ];
+
+    pub fn from_name(name:&str) -> Option<Self>{
+        match name{
+            "normal" => Some(BlendMode::Normal),
+            "multiply" => Some(BlendMode::Multiply),
+            "screen" => Some(BlendMode::Screen),
+            "overlay" => Some(BlendMode::Overlay),
+            "add" => Some(BlendMode::Add),
+            "darken" => Some(BlendMode::Darken),
+            "lighten" => Some(BlendMode::Lighten),
+            "difference" => Some(BlendMode::Difference),
+            "replace" => Some(BlendMode::Replace),
+            _ => None
+        }
+    }
+
+    /// Blends two normalized channels, `src` being painted over `dst`.
+    /// Only integer depths clamp `add`, float canvases keep the values above 1
+    fn channel(&self, src:f32, dst:f32, depth:Depth) -> f32{
+        match self{
+            BlendMode::Normal | BlendMode::Replace => src,
+            BlendMode::Multiply => src * dst,
+            BlendMode::Screen => src + dst - src * dst,
+            BlendMode::Overlay => {
+                if dst <= 0.5 { 2.0 * src * dst } else { 1.0 - 2.0 * (1.0 - src) * (1.0 - dst) }
+            },
+            BlendMode::Add if depth == Depth::Rgba32F => src + dst,
+            BlendMode::Add => (src + dst).min(1.0),
+            BlendMode::Darken => src.min(dst),
+            BlendMode::Lighten => src.max(dst),
+            BlendMode::Difference => (src - dst).abs()
+        }
+    }
+
+    /// Source-over compositing of `src` on `dst`, both made of normalized channels, with the source alpha scaled by `coverage`.
+    /// `replace` mixes the colors by `coverage` instead. `depth` is the one of the canvas holding `dst`
+    pub fn composite(&self, src:[f32; 4], dst:[f32; 4], coverage:f32, depth:Depth) -> [f32; 4]{
+        let coverage = coverage.clamp(0.0, 1.0);
+        if *self == BlendMode::Replace{
+            return [0, 1, 2, 3].map(|i| dst[i] + (src[i] - dst[i]) * coverage);
+        }
+
+        let [sr, sg, sb, sa] = src;
+        let [dr, dg, db, da] = dst;
+
+        // An opaque color covers whatever is below it
+        let sa = sa * coverage;
+        if *self == BlendMode::Normal && sa >= 1.0{
+            return src;
+        }
+
+        let out_a = sa + da * (1.0 - sa);
+
+        if out_a <= 0.0{
+            return [0.0; 4];
+        }
+
+        // Where the destination is transparent the source color is kept as is
+        let channel = |s:f32, d:f32| {
+            let mixed = (1.0 - da) * s + da * self.channel(s, d, depth);
+            (mixed * sa + d * da * (1.0 - sa)) / out_a
+        };
+
+        [channel(sr, dr), channel(sg, dg), channel(sb, db), out_a]
+    }
+}
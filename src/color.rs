@@ -0,0 +1,205 @@
+use crate::{vm::Canvas, blend::BlendMode, to_rgba, from_rgba};
+
+/// Space in which colors are interpolated
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ColorSpace{
+    Srgb,
+    Linear,
+    Lab
+}
+
+impl ColorSpace{
+    pub const NAMES:&'static [&'static str] = &["srgb", "linear", "lab"];
+
+    pub fn from_name(name:&str) -> Option<Self>{
+        match name{
+            "srgb" => Some(ColorSpace::Srgb),
+            "linear" => Some(ColorSpace::Linear),
+            "lab" => Some(ColorSpace::Lab),
+            _ => None
+        }
+    }
+
+    /// Converts normalized sRGB channels to this space
+    fn encode(&self, rgb:[f32; 3]) -> [f32; 3]{
+        match self{
+            ColorSpace::Srgb => rgb,
+            ColorSpace::Linear => rgb.map(to_linear),
+            ColorSpace::Lab => linear_to_lab(rgb.map(to_linear))
+        }
+    }
+
+    /// Converts channels of this space back to sRGB
+    fn decode(&self, c:[f32; 3]) -> [f32; 3]{
+        match self{
+            ColorSpace::Srgb => c,
+            ColorSpace::Linear => c.map(to_srgb),
+            ColorSpace::Lab => lab_to_linear(c).map(to_srgb)
+        }
+    }
+}
+
+/// D65 white point
+const WHITE:[f32; 3] = [0.95047, 1.0, 1.08883];
+
+fn to_linear(c:f32) -> f32{
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+fn to_srgb(c:f32) -> f32{
+    let c = c.clamp(0.0, 1.0);
+    if c <= 0.0031308 { c * 12.92 } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 }
+}
+
+fn linear_to_lab([r, g, b]:[f32; 3]) -> [f32; 3]{
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / WHITE[0];
+    let y = (0.2126 * r + 0.7152 * g + 0.0722 * b) / WHITE[1];
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / WHITE[2];
+
+    let f = |t:f32| if t > 0.008856 { t.cbrt() } else { 7.787 * t + 16.0 / 116.0 };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+fn lab_to_linear([l, a, b]:[f32; 3]) -> [f32; 3]{
+    let fy = (l + 16.0) / 116.0;
+    let fx = fy + a / 500.0;
+    let fz = fy - b / 200.0;
+
+    let f = |t:f32| if t.powi(3) > 0.008856 { t.powi(3) } else { (t - 16.0 / 116.0) / 7.787 };
+    let (x, y, z) = (f(fx) * WHITE[0], f(fy) * WHITE[1], f(fz) * WHITE[2]);
+
+    [
+        3.2406 * x - 1.5372 * y - 0.4986 * z,
+        -0.9689 * x + 1.8758 * y + 0.0415 * z,
+        0.0557 * x - 0.2040 * y + 1.0570 * z
+    ]
+}
+
+fn normalized(color:u32) -> [f32; 4]{
+    to_rgba(color).map(|e| e as f32 / 255.0)
+}
+
+fn packed([r, g, b]:[f32; 3], a:f32) -> u32{
+    let channel = |c:f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+    from_rgba(channel(r), channel(g), channel(b), channel(a))
+}
+
+/// Red, green and blue of the hue `h` in degrees, before saturation and value are applied
+fn hue_to_rgb(h:f32) -> [f32; 3]{
+    let h = h.rem_euclid(360.0) / 60.0;
+    let x = 1.0 - (h % 2.0 - 1.0).abs();
+
+    match h as u32{
+        0 => [1.0, x, 0.0],
+        1 => [x, 1.0, 0.0],
+        2 => [0.0, 1.0, x],
+        3 => [0.0, x, 1.0],
+        4 => [x, 0.0, 1.0],
+        _ => [1.0, 0.0, x]
+    }
+}
+
+/// Opaque color of hue `h` in degrees, saturation `s` and value `v` between 0 and 1
+pub fn hsv(h:f32, s:f32, v:f32) -> u32{
+    let (s, v) = (s.clamp(0.0, 1.0), v.clamp(0.0, 1.0));
+    let chroma = v * s;
+    let min = v - chroma;
+
+    packed(hue_to_rgb(h).map(|e| e * chroma + min), 1.0)
+}
+
+/// Opaque color of hue `h` in degrees, saturation `s` and lightness `l` between 0 and 1
+pub fn hsl(h:f32, s:f32, l:f32) -> u32{
+    let (s, l) = (s.clamp(0.0, 1.0), l.clamp(0.0, 1.0));
+    let chroma = (1.0 - (2.0 * l - 1.0).abs()) * s;
+    let min = l - chroma / 2.0;
+
+    packed(hue_to_rgb(h).map(|e| e * chroma + min), 1.0)
+}
+
+/// Hue of `color` in degrees, between 0 and 360. Grays have a hue of 0
+pub fn hue(color:u32) -> f32{
+    let [r, g, b, _] = normalized(color);
+    let max = r.max(g).max(b);
+    let chroma = max - r.min(g).min(b);
+
+    if chroma == 0.0{
+        return 0.0;
+    }
+
+    let h = if max == r{
+        ((g - b) / chroma).rem_euclid(6.0)
+    }else if max == g{
+        (b - r) / chroma + 2.0
+    }else{
+        (r - g) / chroma + 4.0
+    };
+
+    h * 60.0
+}
+
+/// HSL lightness of `color`, between 0 and 1
+pub fn lightness(color:u32) -> f32{
+    let [r, g, b, _] = normalized(color);
+    (r.max(g).max(b) + r.min(g).min(b)) / 2.0
+}
+
+/// HSL saturation of `color`, between 0 and 1
+pub fn saturation(color:u32) -> f32{
+    let [r, g, b, _] = normalized(color);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let l = (max + min) / 2.0;
+
+    if max == min{
+        0.0
+    }else{
+        (max - min) / (1.0 - (2.0 * l - 1.0).abs())
+    }
+}
+
+/// Interpolates from `from` to `to` by `t`, between 0 and 1, in `space`. The alpha is interpolated linearly
+pub fn mix(from:u32, to:u32, t:f32, space:ColorSpace) -> u32{
+    let t = t.clamp(0.0, 1.0);
+    let [r0, g0, b0, a0] = normalized(from);
+    let [r1, g1, b1, a1] = normalized(to);
+
+    let c0 = space.encode([r0, g0, b0]);
+    let c1 = space.encode([r1, g1, b1]);
+
+    let c = [0, 1, 2].map(|i| c0[i] + (c1[i] - c0[i]) * t);
+
+    packed(space.decode(c), a0 + (a1 - a0) * t)
+}
+
+impl Canvas{
+    /// Fills the canvas with a gradient from `from` at (`x0`, `y0`) to `to` at (`x1`, `y1`), blended with `mode`
+    #[allow(clippy::too_many_arguments)]
+    pub fn gradient_linear(&mut self, x0:i32, y0:i32, x1:i32, y1:i32, from:u32, to:u32, space:ColorSpace, mode:BlendMode){
+        let (dx, dy) = ((x1 - x0) as f32, (y1 - y0) as f32);
+        let length = dx * dx + dy * dy;
+
+        for y in 0..self.height{
+            for x in 0..self.width{
+                let t = if length == 0.0 { 0.0 } else {
+                    ((x as i32 - x0) as f32 * dx + (y as i32 - y0) as f32 * dy) / length
+                };
+                self.blend(x, y, mix(from, to, t, space), 1.0, mode);
+            }
+        }
+    }
+
+    /// Fills the canvas with a gradient from `from` at (`cx`, `cy`) to `to` at `radius` pixels from it and beyond, blended with `mode`
+    #[allow(clippy::too_many_arguments)]
+    pub fn gradient_radial(&mut self, cx:i32, cy:i32, radius:i32, from:u32, to:u32, space:ColorSpace, mode:BlendMode){
+        for y in 0..self.height{
+            for x in 0..self.width{
+                let (dx, dy) = ((x as i32 - cx) as f32, (y as i32 - cy) as f32);
+                let t = if radius <= 0 { 1.0 } else { (dx * dx + dy * dy).sqrt() / radius as f32 };
+                self.blend(x, y, mix(from, to, t, space), 1.0, mode);
+            }
+        }
+    }
+}
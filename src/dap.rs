@@ -0,0 +1,410 @@
+//! Debug Adapter Protocol server, letting an editor debug a script with `Program::debug`
+
+use std::{collections::HashMap, env, io::{self, BufRead, Write}, path::{Path, PathBuf}};
+
+use serde_json::{json, Value as Json};
+
+use crate::{Engine, Program, Function, Value, Debugger, Paused, Stop, Resume};
+
+/// The only thread of a script
+const THREAD_ID:i64 = 1;
+
+/// Function run when the launch request doesn't give one
+const DEFAULT_ENTRY:&str = "main";
+
+/// Script to run, from the launch request
+struct Launch{
+    program: Program,
+    entry: String,
+    args: Vec<Value>,
+    stop_on_entry: bool
+}
+
+struct Server<R, W>{
+    input: R,
+    output: W,
+    /// Sequence number of the next message sent
+    seq: i64,
+    launch: Option<Launch>,
+    /// Lines with a breakpoint, by script
+    breakpoints: HashMap<PathBuf, Vec<usize>>,
+    /// The client disconnected or can't be read from anymore
+    done: bool,
+    error: Option<io::Error>
+}
+
+/// Answers the requests read from `input` on `output`, until the client disconnects or `input` ends.
+/// Messages are framed by a `Content-Length` header as the protocol expects, such as on stdin and stdout
+pub fn serve(input:impl BufRead, output:impl Write) -> io::Result<()>{
+    let mut server = Server{ input, output, seq: 1, launch: None, breakpoints: HashMap::new(), done: false, error: None };
+
+    while !server.done{
+        match server.read(){
+            Some(request) => {
+                server.handle(&request, None);
+            },
+            None => break
+        }
+    }
+
+    match server.error{
+        Some(e) => Err(e),
+        None => Ok(())
+    }
+}
+
+impl<R:BufRead, W:Write> Server<R, W>{
+    /// Next request of the client, `None` once it can't be read.
+    /// A message that isn't a JSON object is answered with an error and skipped
+    fn read(&mut self) -> Option<Json>{
+        while !self.done{
+            let body = match self.read_message(){
+                Ok(body) => body?,
+                Err(e) => {
+                    self.error = Some(e);
+                    return None;
+                }
+            };
+
+            match serde_json::from_slice::<Json>(&body){
+                Ok(request) if request.is_object() => return Some(request),
+                Ok(_) => self.respond(&Json::Null, Err("Invalid message: expected a JSON object".to_string())),
+                Err(e) => self.respond(&Json::Null, Err(format!("Invalid message: {e}")))
+            }
+        }
+
+        None
+    }
+
+    /// Body of the next message, `None` at the end of the input
+    fn read_message(&mut self) -> io::Result<Option<Vec<u8>>>{
+        let mut length = None;
+
+        loop{
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0{
+                return Ok(None);
+            }
+
+            let line = line.trim_end();
+            if let Some(value) = line.strip_prefix("Content-Length:"){
+                length = value.trim().parse::<usize>().ok();
+            }else if line.is_empty() && length.is_some(){
+                break;
+            }
+        }
+
+        let mut body = vec![0; length.unwrap_or_default()];
+        self.input.read_exact(&mut body)?;
+        Ok(Some(body))
+    }
+
+    fn send(&mut self, kind:&str, mut message:Json){
+        message["seq"] = self.seq.into();
+        message["type"] = kind.into();
+        self.seq += 1;
+
+        let text = message.to_string();
+        let result = write!(self.output, "Content-Length: {}\r\n\r\n{text}", text.len()).and_then(|_| self.output.flush());
+
+        if let Err(e) = result{
+            self.error = Some(e);
+            self.done = true;
+        }
+    }
+
+    fn respond(&mut self, request:&Json, body:Result<Json, String>){
+        let mut response = json!({
+            "request_seq": request["seq"].as_i64().unwrap_or_default(),
+            "command": request["command"].as_str().unwrap_or_default(),
+            "success": body.is_ok()
+        });
+
+        match body{
+            Ok(Json::Null) => {},
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = message.into()
+        }
+
+        self.send("response", response);
+    }
+
+    fn event(&mut self, event:&str, body:Json){
+        let mut message = json!({ "event": event });
+        if !body.is_null(){
+            message["body"] = body;
+        }
+
+        self.send("event", message);
+    }
+
+    fn output(&mut self, category:&str, text:&str){
+        self.event("output", json!({ "category": category, "output": format!("{text}\n") }));
+    }
+
+    /// Answers `request`, `paused` being the state of the script when it is stopped.
+    /// Gives how the script goes on for the requests that resume it
+    fn handle(&mut self, request:&Json, paused:Option<&Paused>) -> Option<Resume>{
+        let command = request["command"].as_str().unwrap_or_default().to_string();
+        let args = &request["arguments"];
+        let mut resume = None;
+
+        let body = match (command.as_str(), paused){
+            ("initialize", _) => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsTerminateRequest": true
+            })),
+
+            ("launch", None) => self.launch(args),
+            ("setBreakpoints", _) => self.set_breakpoints(args),
+            ("configurationDone", _) => Ok(Json::Null),
+            ("threads", _) => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": DEFAULT_ENTRY }] })),
+
+            ("stackTrace", Some(paused)) => Ok(stack_trace(paused)),
+            ("scopes", Some(paused)) => scopes(paused, args),
+            ("variables", Some(paused)) => variables(paused, args),
+            ("pause", Some(_)) => Ok(Json::Null),
+
+            ("continue" | "next" | "stepIn" | "stepOut", Some(_)) => {
+                resume = Some(match command.as_str(){
+                    "continue" => Resume::Continue,
+                    "next" => Resume::StepOver,
+                    "stepIn" => Resume::StepInto,
+                    _ => Resume::StepOut
+                });
+                Ok(json!({ "allThreadsContinued": true }))
+            },
+
+            ("terminate", _) => {
+                resume = Some(Resume::Terminate);
+                Ok(Json::Null)
+            },
+
+            ("disconnect", _) => {
+                resume = Some(Resume::Terminate);
+                self.done = true;
+                Ok(Json::Null)
+            },
+
+            ("launch", Some(_)) => Err("The script is already running".to_string()),
+            ("stackTrace" | "scopes" | "variables" | "pause" | "continue" | "next" | "stepIn" | "stepOut", None) => Err("The script is not stopped".to_string()),
+            _ => Err(format!("Unsupported request {command}"))
+        };
+
+        let success = body.is_ok();
+        self.respond(request, body);
+
+        if success && command == "launch"{
+            self.event("initialized", Json::Null);
+        }
+
+        if success && command == "configurationDone" && paused.is_none(){
+            self.run();
+        }
+
+        resume
+    }
+
+    /// Compiles the script of a launch request: `program` is its path, and the optional `entry`, `args`, `seed`
+    /// and `stopOnEntry` are the function run, its arguments, the seed of the random number generator and whether it stops before its first statement
+    fn launch(&mut self, args:&Json) -> Result<Json, String>{
+        let Some(path) = args["program"].as_str() else {
+            return Err("Expected the path of the script as 'program'".to_string());
+        };
+
+        let path = env::current_dir().map(|e| e.join(path)).unwrap_or_else(|_| PathBuf::from(path));
+        let mut program = Engine::new().compile(&path).map_err(|e| e.to_string())?;
+
+        if let Some(seed) = args["seed"].as_i64(){
+            program.set_seed(seed as u32);
+        }
+
+        let entry = args["entry"].as_str().unwrap_or(DEFAULT_ENTRY).to_string();
+        let overloads = program.functions(&entry).into_iter().filter(|e| e.public || e.name == DEFAULT_ENTRY).collect::<Vec<&Function>>();
+        if overloads.is_empty(){
+            return match program.function(&entry){
+                Some(_) => Err(format!("The function {entry} is not public, only {DEFAULT_ENTRY} and public functions can be run")),
+                None => Err(format!("No function named {entry} in {}", path.display()))
+            };
+        }
+
+        // An overloaded function is its first definition able to read the arguments
+        let texts = args["args"].as_array().map(Vec::as_slice).unwrap_or_default();
+        let mut parsed = overloads.iter().map(|function| {
+            if texts.len() != function.params.len(){
+                return Err(format!("{entry} takes {} arguments but {} were given", function.params.len(), texts.len()));
+            }
+
+            function.params.iter().zip(texts).map(|((param, t), text)| {
+                let text = match text{
+                    Json::String(s) => s.clone(),
+                    other => other.to_string()
+                };
+                Value::parse(&text, *t).map_err(|e| format!("Invalid argument '{param}' of {entry}: {e}"))
+            }).collect::<Result<Vec<Value>, String>>()
+        }).collect::<Vec<Result<Vec<Value>, String>>>();
+
+        let values = match parsed.iter().position(Result::is_ok){
+            Some(i) => parsed.swap_remove(i)?,
+            None if parsed.len() == 1 => parsed.swap_remove(0)?,
+            None => {
+                let errors = overloads.iter().zip(parsed).map(|(function, e)| format!("{function}: {}", e.unwrap_err())).collect::<Vec<String>>();
+                return Err(format!("No definition of {entry} takes these arguments: {}", errors.join("; ")));
+            }
+        };
+
+        let stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+        self.launch = Some(Launch{ program, entry, args: values, stop_on_entry });
+
+        Ok(Json::Null)
+    }
+
+    /// Replaces the breakpoints of a script, each one being moved to the first statement starting at its line or after
+    fn set_breakpoints(&mut self, args:&Json) -> Result<Json, String>{
+        let Some(path) = args["source"]["path"].as_str() else {
+            return Err("Expected the path of the script as 'source.path'".to_string());
+        };
+        let path = PathBuf::from(path);
+
+        let statements = self.launch.as_ref().map(|e| e.program.statement_lines(&path)).unwrap_or_default();
+        let requested = args["breakpoints"].as_array().map(Vec::as_slice).unwrap_or_default();
+
+        let mut lines = vec![];
+        let mut breakpoints = vec![];
+
+        for breakpoint in requested{
+            let line = breakpoint["line"].as_u64().unwrap_or_default() as usize;
+
+            match statements.iter().find(|e| **e >= line){
+                Some(statement) => {
+                    lines.push(*statement);
+                    breakpoints.push(json!({ "verified": true, "line": statement }));
+                },
+                None => breakpoints.push(json!({
+                    "verified": false,
+                    "line": line,
+                    "message": "No statement at this line or after it"
+                }))
+            }
+        }
+
+        self.breakpoints.insert(path, lines);
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    /// Runs the launched script, then tells the client it ended
+    fn run(&mut self){
+        let Some(launch) = self.launch.take() else {
+            self.output("stderr", "Nothing to run, the launch request failed");
+            self.event("terminated", Json::Null);
+            return;
+        };
+
+        let result = launch.program.debug(&launch.entry, &launch.args, self, launch.stop_on_entry);
+        let exit_code = match result{
+            Ok(saved) => {
+                self.output("console", &format!("The script ended, {} canvas(es) saved", saved.len()));
+                0
+            },
+            Err(e) => {
+                self.output("stderr", &format!("Runtime error: {e}"));
+                1
+            }
+        };
+
+        self.event("exited", json!({ "exitCode": exit_code }));
+        self.event("terminated", Json::Null);
+        self.launch = Some(launch);
+    }
+}
+
+impl<R:BufRead, W:Write> Debugger for Server<R, W>{
+    fn breakpoint(&self, path:&Path, line:usize) -> bool{
+        self.breakpoints.get(path).is_some_and(|e| e.contains(&line))
+    }
+
+    fn stopped(&mut self, paused:&Paused, reason:Stop) -> Resume{
+        let mut body = json!({ "threadId": THREAD_ID, "allThreadsStopped": true });
+
+        body["reason"] = match reason{
+            Stop::Entry => "entry",
+            Stop::Breakpoint => "breakpoint",
+            Stop::Step => "step",
+            Stop::Error(e) => {
+                body["text"] = e.into();
+                "exception"
+            }
+        }.into();
+        self.event("stopped", body);
+
+        while !self.done{
+            let Some(request) = self.read() else {
+                break;
+            };
+
+            if let Some(resume) = self.handle(&request, Some(paused)){
+                return resume;
+            }
+        }
+
+        self.done = true;
+        Resume::Terminate
+    }
+}
+
+fn stack_trace(paused:&Paused) -> Json{
+    let frames = paused.frames.iter().enumerate().map(|(i, frame)| json!({
+        "id": i,
+        "name": frame.function,
+        "source": {
+            "name": frame.script.file_name().map(|e| e.to_string_lossy()).unwrap_or_default(),
+            "path": frame.script.to_string_lossy()
+        },
+        "line": frame.line,
+        "column": 1
+    })).collect::<Vec<Json>>();
+
+    json!({ "stackFrames": frames, "totalFrames": paused.frames.len() })
+}
+
+/// Variables reference of the canvas stack, the ones of the frames going from 1 to the number of frames
+fn canvases_reference(paused:&Paused) -> usize{
+    paused.frames.len() + 1
+}
+
+fn scopes(paused:&Paused, args:&Json) -> Result<Json, String>{
+    let id = args["frameId"].as_i64().unwrap_or(-1);
+    if id < 0 || id as usize >= paused.frames.len(){
+        return Err(format!("No frame {id}"));
+    }
+
+    let scope = |name:&str, reference:usize| json!({ "name": name, "variablesReference": reference, "expensive": false });
+
+    Ok(json!({ "scopes": [scope("Locals", id as usize + 1), scope("Canvases", canvases_reference(paused))] }))
+}
+
+fn variables(paused:&Paused, args:&Json) -> Result<Json, String>{
+    let reference = args["variablesReference"].as_u64().unwrap_or_default() as usize;
+
+    let variable = |name:String, value:String, t:String| json!({
+        "name": name,
+        "value": value,
+        "type": t,
+        "variablesReference": 0
+    });
+
+    let variables = if reference == canvases_reference(paused){
+        paused.canvases.iter().enumerate().map(|(i, canvas)| {
+            let layer = if canvas.layer.is_some() { ", layer" } else { "" };
+            variable(i.to_string(), format!("{}x{}{layer}", canvas.width, canvas.height), "canvas".to_string())
+        }).collect::<Vec<Json>>()
+
+    }else if let Some(frame) = reference.checked_sub(1).and_then(|i| paused.frames.get(i)){
+        frame.locals.iter().map(|(name, value)| variable(name.clone(), value.to_string(), value.type_of().to_string())).collect()
+
+    }else{
+        return Err(format!("No variables for the reference {reference}"));
+    };
+
+    Ok(json!({ "variables": variables }))
+}
@@ -0,0 +1,359 @@
+use crate::{vm::{Canvas, MAX_CANVAS_SIZE}, blend::BlendMode};
+
+/// Drawing state of the VM, shared by every primitive
+#[derive(Debug, Clone, Copy)]
+pub struct Pen{
+    pub antialias: bool,
+    pub stroke: u32,
+    pub blend: BlendMode
+}
+
+impl Default for Pen{
+    fn default() -> Self {
+        Pen { antialias: false, stroke: 1, blend: BlendMode::Normal }
+    }
+}
+
+impl Pen{
+    /// Aliased one pixel wide outlines keep the exact integer algorithms
+    fn is_exact(&self) -> bool{
+        !self.antialias && self.stroke <= 1
+    }
+
+    fn half_width(&self) -> f32{
+        self.stroke.max(1) as f32 / 2.0
+    }
+
+    /// Pixel coverage from the signed distance of its center to a shape, negative inside
+    fn coverage(&self, distance:f32) -> f32{
+        if self.antialias{
+            (0.5 - distance).clamp(0.0, 1.0)
+        }else if distance <= 0.0{
+            1.0
+        }else{
+            0.0
+        }
+    }
+}
+
+type Point = (f32, f32);
+
+fn segment_distance(px:f32, py:f32, (ax, ay):Point, (bx, by):Point) -> f32{
+    let (dx, dy) = (bx - ax, by - ay);
+    let len = dx * dx + dy * dy;
+    let t = if len == 0.0 { 0.0 } else { (((px - ax) * dx + (py - ay) * dy) / len).clamp(0.0, 1.0) };
+
+    let (cx, cy) = (ax + dx * t - px, ay + dy * t - py);
+    (cx * cx + cy * cy).sqrt()
+}
+
+/// Approximate signed distance to an axis aligned ellipse centered on the origin
+fn ellipse_distance(px:f32, py:f32, rx:f32, ry:f32) -> f32{
+    let k0 = ((px / rx).powi(2) + (py / ry).powi(2)).sqrt();
+    let k1 = ((px / (rx * rx)).powi(2) + (py / (ry * ry)).powi(2)).sqrt();
+
+    if k1 == 0.0 { -rx.min(ry) } else { k0 * (k0 - 1.0) / k1 }
+}
+
+/// Shape rasterization on a canvas, everything outside of the canvas is clipped
+impl Canvas{
+    /// Blends a covered pixel over the current one, whatever its coverage, so the inside and the edges of a shape composite alike
+    pub(crate) fn plot(&mut self, x:i32, y:i32, pixel:u32, coverage:f32, mode:BlendMode){
+        if coverage > 0.0 && (0 <= x && x < self.width as i32) && (0 <= y && y < self.height as i32){
+            self.blend(x as u32, y as u32, pixel, coverage, mode);
+        }
+    }
+
+    /// Evaluates `distance` on every pixel center of the bounds and plots its coverage
+    fn shade(&mut self, (min_x, min_y):Point, (max_x, max_y):Point, pixel:u32, pen:&Pen, distance:impl Fn(f32, f32) -> f32){
+        let start_x = (min_x.floor() as i32).max(0);
+        let end_x = (max_x.ceil() as i32).min(self.width as i32 - 1);
+        let start_y = (min_y.floor() as i32).max(0);
+        let end_y = (max_y.ceil() as i32).min(self.height as i32 - 1);
+
+        for y in start_y..=end_y{
+            for x in start_x..=end_x{
+                let coverage = pen.coverage(distance(x as f32, y as f32));
+                self.plot(x, y, pixel, coverage, pen.blend);
+            }
+        }
+    }
+
+    /// Strokes a list of segments as a single shape so that joints are not drawn twice
+    fn stroke_segments(&mut self, segments:&[(Point, Point)], pixel:u32, pen:&Pen){
+        let margin = pen.half_width() + 1.0;
+        let points = segments.iter().flat_map(|&(a, b)| [a, b]);
+        let min_x = points.clone().map(|p| p.0).fold(f32::MAX, f32::min) - margin;
+        let min_y = points.clone().map(|p| p.1).fold(f32::MAX, f32::min) - margin;
+        let max_x = points.clone().map(|p| p.0).fold(f32::MIN, f32::max) + margin;
+        let max_y = points.map(|p| p.1).fold(f32::MIN, f32::max) + margin;
+
+        let half = pen.half_width();
+        self.shade((min_x, min_y), (max_x, max_y), pixel, pen, |x, y| {
+            segments.iter().map(|&(a, b)| segment_distance(x, y, a, b))
+                .fold(f32::MAX, f32::min) - half
+        });
+    }
+
+    fn span(&mut self, y:i32, x0:i32, x1:i32, pixel:u32, mode:BlendMode){
+        if y < 0 || y >= self.height as i32{
+            return;
+        }
+
+        let start = x0.min(x1).max(0);
+        let end = x0.max(x1).min(self.width as i32 - 1);
+
+        for x in start..=end{
+            self.blend(x as u32, y as u32, pixel, 1.0, mode);
+        }
+    }
+
+    pub fn draw_line(&mut self, x0:i32, y0:i32, x1:i32, y1:i32, pixel:u32, pen:&Pen){
+        if !pen.is_exact(){
+            let (a, b) = ((x0 as f32, y0 as f32), (x1 as f32, y1 as f32));
+            self.stroke_segments(&[(a, b)], pixel, pen);
+            return;
+        }
+
+        // Bresenham's pixels, each one computed from its step along the major axis
+        // so that only the steps crossing the canvas are walked
+        let (x0, y0, x1, y1) = (x0 as i64, y0 as i64, x1 as i64, y1 as i64);
+        let (dx, dy) = ((x1 - x0).abs(), (y1 - y0).abs());
+        let (sx, sy) = ((x1 - x0).signum(), (y1 - y0).signum());
+
+        let x_major = dx >= dy;
+        let (major, minor, start, step, size) = if x_major{
+            (dx, dy, x0, sx, self.width as i64)
+        }else{
+            (dy, dx, y0, sy, self.height as i64)
+        };
+
+        let (first, last) = if step >= 0 { (-start, size - 1 - start) } else { (start - size + 1, start) };
+
+        for k in first.max(0)..=last.min(major){
+            let m = if major == 0 { 0 } else { (2 * k * minor + major) / (2 * major) };
+            let (x, y) = if x_major { (x0 + sx * k, y0 + sy * m) } else { (x0 + sx * m, y0 + sy * k) };
+            self.plot(x as i32, y as i32, pixel, 1.0, pen.blend);
+        }
+    }
+
+    pub fn draw_rect(&mut self, x:i32, y:i32, w:i32, h:i32, pixel:u32, pen:&Pen){
+        if w <= 0 || h <= 0{
+            return;
+        }
+
+        let (x1, y1) = (x.saturating_add(w - 1), y.saturating_add(h - 1));
+
+        if !pen.is_exact(){
+            let corners = [(x as f32, y as f32), (x1 as f32, y as f32), (x1 as f32, y1 as f32), (x as f32, y1 as f32)];
+            let segments = [(corners[0], corners[1]), (corners[1], corners[2]), (corners[2], corners[3]), (corners[3], corners[0])];
+            self.stroke_segments(&segments, pixel, pen);
+            return;
+        }
+
+        self.span(y, x, x1, pixel, pen.blend);
+        if y1 != y{
+            self.span(y1, x, x1, pixel, pen.blend);
+        }
+
+        for j in y.saturating_add(1).max(0)..y1.min(self.height as i32){
+            self.plot(x, j, pixel, 1.0, pen.blend);
+            if x1 != x{
+                self.plot(x1, j, pixel, 1.0, pen.blend);
+            }
+        }
+    }
+
+    /// Rectangles lie on whole pixels, so only the blend mode of the pen is used here
+    pub fn fill_rect(&mut self, x:i32, y:i32, w:i32, h:i32, pixel:u32, pen:&Pen){
+        if w <= 0 || h <= 0{
+            return;
+        }
+
+        let (x1, y1) = (x.saturating_add(w - 1), y.saturating_add(h - 1));
+        for j in y.max(0)..=y1.min(self.height as i32 - 1){
+            self.span(j, x, x1, pixel, pen.blend);
+        }
+    }
+
+    pub fn draw_circle(&mut self, cx:i32, cy:i32, r:i32, pixel:u32, pen:&Pen){
+        self.draw_ellipse(cx, cy, r, r, pixel, pen);
+    }
+
+    pub fn fill_circle(&mut self, cx:i32, cy:i32, r:i32, pixel:u32, pen:&Pen){
+        self.fill_ellipse(cx, cy, r, r, pixel, pen);
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_ellipse(&mut self, cx:i32, cy:i32, rx:i32, ry:i32, pixel:u32, pen:&Pen){
+        if rx < 0 || ry < 0{
+            return;
+        }
+
+        if rx == 0 || ry == 0{
+            self.draw_line(cx.saturating_sub(rx), cy.saturating_sub(ry), cx.saturating_add(rx), cy.saturating_add(ry), pixel, pen);
+            return;
+        }
+
+        // Past the size of the largest canvas, the midpoint algorithm walks far more pixels than it can draw
+        if !pen.is_exact() || rx.max(ry) > MAX_CANVAS_SIZE{
+            let (fx, fy, frx, fry) = (cx as f32, cy as f32, rx as f32, ry as f32);
+            let margin = pen.half_width() + 1.0;
+            let half = pen.half_width();
+
+            self.shade((fx - frx - margin, fy - fry - margin), (fx + frx + margin, fy + fry + margin), pixel, pen, |x, y| {
+                ellipse_distance(x - fx, y - fy, frx, fry).abs() - half
+            });
+            return;
+        }
+
+        // Midpoint ellipse algorithm, one quadrant mirrored four times
+        let (rx2, ry2) = (rx as i64 * rx as i64, ry as i64 * ry as i64);
+        let (mut x, mut y) = (0i64, ry as i64);
+        let (mut px, mut py) = (0i64, 2 * rx2 * y);
+
+        // The points on the axes are their own mirror, they are only blended once
+        let plot = |canvas: &mut Canvas, x:i64, y:i64| {
+            let (x, y) = (x as i32, y as i32);
+            let xs = if x == 0 { vec![cx] } else { vec![cx + x, cx - x] };
+            let ys = if y == 0 { vec![cy] } else { vec![cy + y, cy - y] };
+
+            for &py in &ys{
+                for &px in &xs{
+                    canvas.plot(px, py, pixel, 1.0, pen.blend);
+                }
+            }
+        };
+
+        let mut p = ry2 - rx2 * ry as i64 + rx2 / 4;
+        while px < py{
+            plot(self, x, y);
+            x += 1;
+            px += 2 * ry2;
+            if p < 0{
+                p += ry2 + px;
+            }else{
+                y -= 1;
+                py -= 2 * rx2;
+                p += ry2 + px - py;
+            }
+        }
+
+        p = ry2 * (2 * x + 1) * (2 * x + 1) / 4 + rx2 * (y - 1) * (y - 1) - rx2 * ry2;
+        while y >= 0{
+            plot(self, x, y);
+            y -= 1;
+            py -= 2 * rx2;
+            if p > 0{
+                p += rx2 - py;
+            }else{
+                x += 1;
+                px += 2 * ry2;
+                p += rx2 - py + px;
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn fill_ellipse(&mut self, cx:i32, cy:i32, rx:i32, ry:i32, pixel:u32, pen:&Pen){
+        if rx < 0 || ry < 0{
+            return;
+        }
+
+        if pen.antialias{
+            // The aliased scanlines stop half a pixel past the radius, so does the coverage
+            let (fx, fy) = (cx as f32, cy as f32);
+            let (frx, fry) = (rx as f32 + 0.5, ry as f32 + 0.5);
+
+            self.shade((fx - frx - 1.0, fy - fry - 1.0), (fx + frx + 1.0, fy + fry + 1.0), pixel, pen, |x, y| {
+                ellipse_distance(x - fx, y - fy, frx, fry)
+            });
+            return;
+        }
+
+        if ry == 0{
+            self.span(cy, cx.saturating_sub(rx), cx.saturating_add(rx), pixel, pen.blend);
+            return;
+        }
+
+        // Only the rows of the canvas are walked
+        let (cy, ry64) = (cy as i64, ry as i64);
+        let first = (-ry64).max(-cy);
+        let last = ry64.min(self.height as i64 - 1 - cy);
+
+        for dy in first..=last{
+            let t = dy as f32 / (ry as f32 + 0.5);
+            let dx = (rx as f32 + 0.5) * (1.0 - t * t).max(0.0).sqrt();
+            let dx = dx.floor() as i32;
+            self.span((cy + dy) as i32, cx.saturating_sub(dx), cx.saturating_add(dx), pixel, pen.blend);
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_triangle(&mut self, x0:i32, y0:i32, x1:i32, y1:i32, x2:i32, y2:i32, pixel:u32, pen:&Pen){
+        if !pen.is_exact(){
+            let (a, b, c) = ((x0 as f32, y0 as f32), (x1 as f32, y1 as f32), (x2 as f32, y2 as f32));
+            self.stroke_segments(&[(a, b), (b, c), (c, a)], pixel, pen);
+            return;
+        }
+
+        self.draw_line(x0, y0, x1, y1, pixel, pen);
+        self.draw_line(x1, y1, x2, y2, pixel, pen);
+        self.draw_line(x2, y2, x0, y0, pixel, pen);
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn fill_triangle(&mut self, x0:i32, y0:i32, x1:i32, y1:i32, x2:i32, y2:i32, pixel:u32, pen:&Pen){
+        let min_x = x0.min(x1).min(x2).max(0);
+        let max_x = x0.max(x1).max(x2).min(self.width as i32 - 1);
+        let min_y = y0.min(y1).min(y2).max(0);
+        let max_y = y0.max(y1).max(y2).min(self.height as i32 - 1);
+
+        let edge = |ax:i32, ay:i32, bx:i32, by:i32, px:i32, py:i32| {
+            (bx as i128 - ax as i128) * (py as i128 - ay as i128) - (by as i128 - ay as i128) * (px as i128 - ax as i128)
+        };
+
+        let area = edge(x0, y0, x1, y1, x2, y2);
+        if area == 0{
+            self.draw_triangle(x0, y0, x1, y1, x2, y2, pixel, pen);
+            return;
+        }
+
+        if pen.antialias{
+            // Distance to the farthest edge line, measured outwards whatever the winding
+            let sign = if area > 0 { -1.0 } else { 1.0 };
+            let edge_distance = |ax:i32, ay:i32, bx:i32, by:i32, px:f32, py:f32| {
+                let (dx, dy) = ((bx - ax) as f32, (by - ay) as f32);
+                sign * (dx * (py - ay as f32) - dy * (px - ax as f32)) / (dx * dx + dy * dy).sqrt()
+            };
+
+            let min = (x0.min(x1).min(x2) as f32 - 1.0, y0.min(y1).min(y2) as f32 - 1.0);
+            let max = (x0.max(x1).max(x2) as f32 + 1.0, y0.max(y1).max(y2) as f32 + 1.0);
+
+            self.shade(min, max, pixel, pen, |x, y| {
+                edge_distance(x1, y1, x2, y2, x, y)
+                    .max(edge_distance(x2, y2, x0, y0, x, y))
+                    .max(edge_distance(x0, y0, x1, y1, x, y))
+            });
+            return;
+        }
+
+        for y in min_y..=max_y{
+            for x in min_x..=max_x{
+                let w0 = edge(x1, y1, x2, y2, x, y);
+                let w1 = edge(x2, y2, x0, y0, x, y);
+                let w2 = edge(x0, y0, x1, y1, x, y);
+
+                let inside = if area > 0{
+                    w0 >= 0 && w1 >= 0 && w2 >= 0
+                }else{
+                    w0 <= 0 && w1 <= 0 && w2 <= 0
+                };
+
+                if inside{
+                    self.blend(x as u32, y as u32, pixel, 1.0, pen.blend);
+                }
+            }
+        }
+    }
+}
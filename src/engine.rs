@@ -0,0 +1,366 @@
+use std::{cell::RefCell, fmt::Display, path::{Path, PathBuf}, rc::Rc};
+
+use neoglot_lib::lexer::Location;
+
+use crate::{ir::{Function, Instruction, Runtime}, session::Session, vm::{VM, Canvas, debug::Debugger}, source::{SourceProvider, FileSystem}, native::Natives, environment::{FuncSign, Type}};
+
+thread_local!{
+    /// Diagnostics reported by the compilation running on this thread
+    static REPORTED:RefCell<Vec<Diagnostic>> = const { RefCell::new(vec![]) };
+}
+
+/// Reports an error found at `location` while compiling
+pub(crate) fn report(msg:&str, location:Location){
+    REPORTED.with(|e| e.borrow_mut().push(Diagnostic{ location: Some(location), message: msg.to_string() }));
+}
+
+/// Reports an error that is not tied to a place in a script
+pub(crate) fn report_message(msg:&str){
+    REPORTED.with(|e| e.borrow_mut().push(Diagnostic{ location: None, message: msg.to_string() }));
+}
+
+/// Takes the diagnostics reported on this thread since the last call
+pub(crate) fn take_reported() -> Vec<Diagnostic>{
+    REPORTED.with(|e| e.take())
+}
+
+/// An error found while compiling a script
+#[derive(Debug, Clone)]
+pub struct Diagnostic{
+    pub location: Option<Location>,
+    pub message: String
+}
+
+impl Display for Diagnostic{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.location{
+            Some(location) => write!(f, "{location}: {}", self.message),
+            None => write!(f, "{}", self.message)
+        }
+    }
+}
+
+/// Every error found while compiling a script and its imports
+#[derive(Debug, Clone, Default)]
+pub struct Diagnostics{
+    pub errors: Vec<Diagnostic>,
+    /// Scripts read before compiling stopped, the ones the errors come from
+    pub scripts: Vec<PathBuf>
+}
+
+impl Display for Diagnostics{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, e) in self.errors.iter().enumerate(){
+            if i > 0{
+                writeln!(f)?;
+            }
+            write!(f, "{e}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Diagnostics{}
+
+/// An error that stopped a running script
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError{
+    pub message: String
+}
+
+impl Display for RuntimeError{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for RuntimeError{}
+
+/// Argument given to the entry point of a program
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value{
+    Int(i32),
+    Float(f32),
+    Bool(bool),
+    /// Packed as `0xRRGGBBAA`
+    Color(u32),
+    String(String),
+    /// Returned by native functions without a return type
+    Void
+}
+
+impl Value{
+    /// Reads a value of type `t` written as in a script, such as `800`, `0.5`, `true` or `#ff0000ff`
+    pub fn parse(text:&str, t:Type) -> Result<Value, String>{
+        let value = match t{
+            Type::Int => text.parse::<i32>().ok().map(Value::Int),
+            Type::Float => text.parse::<f32>().ok().map(Value::Float),
+            Type::Bool => text.parse::<bool>().ok().map(Value::Bool),
+            Type::Color => text.strip_prefix('#')
+                .filter(|e| e.len() == 8)
+                .and_then(|e| u32::from_str_radix(e, 16).ok())
+                .map(Value::Color),
+            Type::String => Some(Value::String(text.to_string())),
+            Type::Void => None
+        };
+
+        value.ok_or_else(|| format!("'{text}' is not a valid {t}"))
+    }
+
+    pub fn type_of(&self) -> Type{
+        match self{
+            Value::Int(_) => Type::Int,
+            Value::Float(_) => Type::Float,
+            Value::Bool(_) => Type::Bool,
+            Value::Color(_) => Type::Color,
+            Value::String(_) => Type::String,
+            Value::Void => Type::Void
+        }
+    }
+}
+
+/// Written as in a script, such as `800`, `0.5`, `true`, `#ff0000ff` or `"text"`
+impl Display for Value{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self{
+            Value::Int(i) => write!(f, "{i}"),
+            Value::Float(x) => write!(f, "{x:?}"),
+            Value::Bool(b) => write!(f, "{b}"),
+            Value::Color(c) => write!(f, "#{c:08x}"),
+            Value::String(s) => write!(f, "\"{s}\""),
+            Value::Void => write!(f, "void")
+        }
+    }
+}
+
+/// What the scripts can use from the program embedding papyrus
+#[derive(Debug, Clone)]
+pub(crate) struct Host{
+    pub sources: Rc<dyn SourceProvider>,
+    pub natives: Rc<Natives>
+}
+
+impl Default for Host{
+    fn default() -> Self {
+        Host{ sources: Rc::new(FileSystem), natives: Rc::default() }
+    }
+}
+
+/// Records the scripts a compilation looks for, to know which ones the program depends on
+#[derive(Debug)]
+struct Tracked{
+    sources: Rc<dyn SourceProvider>,
+    paths: RefCell<Vec<PathBuf>>
+}
+
+impl Tracked{
+    fn track(&self, path:&Path){
+        let mut paths = self.paths.borrow_mut();
+        if !paths.iter().any(|e| e == path){
+            paths.push(path.to_path_buf());
+        }
+    }
+}
+
+impl SourceProvider for Tracked{
+    fn exists(&self, path:&Path) -> bool{
+        self.track(path);
+        self.sources.exists(path)
+    }
+
+    fn read(&self, path:&Path) -> Result<String, String>{
+        self.track(path);
+        self.sources.read(path)
+    }
+
+    fn read_bytes(&self, path:&Path) -> Result<Vec<u8>, String>{
+        self.sources.read_bytes(path)
+    }
+}
+
+/// Compiles papyrus scripts into programs
+#[derive(Debug)]
+pub struct Engine{
+    sources: Rc<dyn SourceProvider>,
+    natives: Natives
+}
+
+impl Default for Engine{
+    fn default() -> Self {
+        Engine{ sources: Rc::new(FileSystem), natives: Natives::new() }
+    }
+}
+
+impl Engine{
+    /// Engine reading the scripts from the disk
+    pub fn new() -> Self{
+        Engine::default()
+    }
+
+    /// Engine reading the scripts from `sources`, such as `MemorySources` to compile source text directly
+    pub fn with_sources(sources:impl SourceProvider + 'static) -> Self{
+        Engine{ sources: Rc::new(sources), natives: Natives::new() }
+    }
+
+    /// Makes the Rust function `func` callable from the scripts compiled afterwards, as `sign` returning `ret`.
+    /// `func` gets one value per parameter and returns a value of type `ret`, or `Value::Void`.
+    /// An error it returns stops the script
+    pub fn register(&mut self, sign:FuncSign, ret:Type, func:impl Fn(&[Value]) -> Result<Value, String> + 'static) -> Result<(), String>{
+        self.natives.register(sign, ret, func)
+    }
+
+    /// Session evaluating code one input at a time, with the sources and native functions of the engine
+    pub fn session(&self) -> Session{
+        Session::new(Host{ sources: self.sources.clone(), natives: Rc::new(self.natives.clone()) })
+    }
+
+    /// Parses, checks and lowers the script at `path` along with its imports.
+    /// `path` is a name given to the source provider, diagnostics refer to the scripts by these names
+    pub fn compile(&self, path:impl AsRef<Path>) -> Result<Program, Diagnostics>{
+        let path = path.as_ref();
+        let Some(file) = path.to_str() else {
+            return Err(Diagnostics{ errors: vec![Diagnostic{ location: None, message: "Non-UTF8 chars found on the filename".to_string() }], scripts: vec![] });
+        };
+
+        REPORTED.with(|e| e.borrow_mut().clear());
+        let tracked = Rc::new(Tracked{ sources: self.sources.clone(), paths: RefCell::default() });
+        let host = Host{ sources: tracked.clone(), natives: Rc::new(self.natives.clone()) };
+        let runtime = crate::parse(file, &host);
+        let errors = REPORTED.with(|e| e.take());
+        let scripts = tracked.paths.take();
+
+        match runtime{
+            Some(runtime) if errors.is_empty() => Ok(Program{ runtime, seed: None, scripts }),
+            None if errors.is_empty() => Err(Diagnostics{ errors: vec![Diagnostic{ location: None, message: format!("Nothing to run in {}", path.display()) }], scripts }),
+            _ => Err(Diagnostics{ errors, scripts })
+        }
+    }
+}
+
+/// A compiled script, ready to be run any number of times
+#[derive(Debug, Clone)]
+pub struct Program{
+    runtime: Runtime,
+    seed: Option<u32>,
+    scripts: Vec<PathBuf>
+}
+
+impl Program{
+    /// Path of the script the program was compiled from
+    pub fn path(&self) -> &Path{
+        &self.runtime.scripts[0].path
+    }
+
+    /// The function `name` of the script, its first definition if it is overloaded
+    pub fn function(&self, name:&str) -> Option<&Function>{
+        self.runtime.scripts[0].functions.iter().find(|e| e.name == name)
+    }
+
+    /// Every definition of the function `name` in the script, in the order of the script
+    pub fn functions(&self, name:&str) -> Vec<&Function>{
+        self.runtime.scripts[0].functions.iter().filter(|e| e.name == name).collect()
+    }
+
+    /// Functions that can be run from the command line: `main` and the public functions of the script
+    pub fn entries(&self) -> impl Iterator<Item = &Function>{
+        self.runtime.scripts[0].functions.iter().filter(|e| e.public || e.name == "main")
+    }
+
+    /// The script the program was compiled from and every script it imports, even indirectly
+    pub fn scripts(&self) -> &[PathBuf]{
+        &self.scripts
+    }
+
+    /// Lines where a statement of the script at `path` starts, in order.
+    /// Empty for a script that is not part of the program
+    pub fn statement_lines(&self, path:&Path) -> Vec<usize>{
+        let mut lines = self.runtime.scripts.iter()
+            .filter(|e| e.path == path)
+            .flat_map(|e| e.program.iter())
+            .filter_map(|e| match e{
+                Instruction::Line(line) => Some(*line),
+                _ => None
+            })
+            .collect::<Vec<usize>>();
+
+        lines.sort();
+        lines.dedup();
+        lines
+    }
+
+    /// Seeds the random number generator so every run gives the same output
+    pub fn set_seed(&mut self, seed:u32){
+        self.seed = Some(seed);
+    }
+
+    /// Runs the function `entry` of the script with `args` as parameters and returns the saved canvases.
+    /// An overloaded `entry` is the definition whose parameters have the types of `args`.
+    /// Fails without running anything if `args` don't match the parameters of `entry`
+    pub fn run(&self, entry:&str, args:&[Value]) -> Result<Vec<Canvas>, RuntimeError>{
+        Ok(self.run_layers(entry, args)?.into_iter().map(|(canvas, _)| canvas).collect())
+    }
+
+    /// Same as `run`, each canvas comes with its layers before they were flattened, from the bottom up.
+    /// A canvas saved without layers has none
+    pub fn run_layers(&self, entry:&str, args:&[Value]) -> Result<Vec<(Canvas, Vec<Canvas>)>, RuntimeError>{
+        let (mut vm, label) = self.start(entry, args)?;
+        self.finish(&mut vm, &label)
+    }
+
+    /// Same as `run_layers`, `debugger` being told about the statements run and stopping the script where it wants.
+    /// The script stops before its first statement if `stop_on_entry` is set
+    pub fn debug(&self, entry:&str, args:&[Value], debugger:&mut dyn Debugger, stop_on_entry:bool) -> Result<Vec<(Canvas, Vec<Canvas>)>, RuntimeError>{
+        let (mut vm, label) = self.start(entry, args)?;
+        vm.set_debugger(debugger, stop_on_entry);
+        self.finish(&mut vm, &label)
+    }
+
+    /// VM ready to run the function `entry` with `args`, and the label of the function
+    fn start<'a>(&self, entry:&str, args:&[Value]) -> Result<(VM<'a>, String), RuntimeError>{
+        let path = self.path().to_path_buf();
+        let types = args.iter().map(Value::type_of).collect::<Vec<Type>>();
+
+        let overloads = self.functions(entry);
+        let function = match overloads[..]{
+            [] => return Err(RuntimeError{ message: format!("No function named {entry} in {}", path.display()) }),
+            [function] => function,
+            _ => match overloads.iter().find(|e| e.params.iter().map(|p| p.1).eq(types.iter().copied())){
+                Some(function) => function,
+                None => {
+                    let types = types.iter().map(Type::to_string).collect::<Vec<String>>().join(", ");
+                    return Err(RuntimeError{ message: format!("No definition of {entry} takes arguments of types ({types})") });
+                }
+            }
+        };
+
+        if args.len() != function.params.len(){
+            return Err(RuntimeError{ message: format!("{entry} takes {} arguments but {} were given", function.params.len(), args.len()) });
+        }
+
+        for ((name, t), arg) in function.params.iter().zip(args){
+            if arg.type_of() != *t{
+                return Err(RuntimeError{ message: format!("The argument '{name}' of {entry} must be of type {t}, got {}", arg.type_of()) });
+            }
+        }
+
+        let mut vm = VM::new(self.runtime.clone());
+        if let Some(seed) = self.seed{
+            vm.set_seed(seed);
+        }
+
+        let args = args.iter().map(|e| vm.encode(e)).collect::<Vec<u32>>();
+        vm.set_args(&args);
+
+        Ok((vm, function.label.clone()))
+    }
+
+    /// Runs the function at `label` and gives the saved canvases with their layers
+    fn finish(&self, vm:&mut VM, label:&str) -> Result<Vec<(Canvas, Vec<Canvas>)>, RuntimeError>{
+        vm.run(self.path(), label).map_err(|message| RuntimeError{ message })?;
+
+        let canvases = vm.get_saved_canvas().iter().cloned();
+        let layers = vm.get_saved_layers().iter().cloned();
+
+        Ok(canvases.zip(layers).collect())
+    }
+}
@@ -2,13 +2,14 @@ use std::{collections::{HashMap, HashSet}, fmt::Display, path::{Path, PathBuf}};
 
 use neoglot_lib::{parser::AST, lexer::Token};
 
-use crate::TokenType;
+use crate::{TokenType, engine::Host, vm::builtins::BUILTINS};
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum Type{
     Int, Float,
     Color,
     Bool,
+    String,
     Void
 }
 
@@ -19,6 +20,20 @@ pub struct FuncSign{
     pub params: Vec<Type>
 }
 
+impl Display for Type{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self{
+            Type::Int => "int",
+            Type::Float => "float",
+            Type::Color => "color",
+            Type::Bool => "bool",
+            Type::String => "string",
+            Type::Void => "void"
+        };
+        write!(f, "{name}")
+    }
+}
+
 impl Display for FuncSign{
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_fmt(format_args!("{}({:?})", self.name, self.params))
@@ -35,117 +50,12 @@ pub struct Environment{
     func_returns: HashMap<FuncSign, Type>,
     imports: HashMap<String, PathBuf>,
     pub public_functions: HashSet<(PathBuf, FuncSign, Type)>,
-    pub cached_imports: HashMap<PathBuf, Vec<AST<Token<TokenType>>>>
+    pub cached_imports: HashMap<PathBuf, Vec<AST<Token<TokenType>>>>,
+    pub host: Host
 }
 
 pub fn builtin_funcs() -> HashMap<FuncSign, Type>{
-    HashMap::from_iter([
-        (FuncSign{
-            name: String::from("create_canvas"),
-            params: vec![Type::Int, Type::Int]
-        }, Type::Void),
-
-        (FuncSign{
-            name: String::from("save_canvas"),
-            params: vec![]
-        }, Type::Void),
-
-        (FuncSign{
-            name: String::from("put"),
-            params: vec![Type::Int, Type::Int, Type::Color]
-        }, Type::Void),
-
-        (FuncSign{
-            name: String::from("fill"),
-            params: vec![Type::Color]
-        }, Type::Void),
-
-        (FuncSign{
-            name: String::from("int"),
-            params: vec![Type::Float]
-        }, Type::Int),
-
-        (FuncSign{
-            name: String::from("float"),
-            params: vec![Type::Int]
-        }, Type::Float),
-
-        (FuncSign{
-            name: String::from("sample"),
-            params: vec![Type::Int, Type::Int]
-        }, Type::Color),
-
-        (FuncSign{
-            name: String::from("width"),
-            params: vec![]
-
-        }, Type::Int),
-
-        (FuncSign{
-            name: String::from("height"),
-            params: vec![]
-
-        }, Type::Int),
-
-        (FuncSign{
-            name: String::from("red"),
-            params: vec![Type::Color]
-
-        }, Type::Int),
-
-        (FuncSign{
-            name: String::from("green"),
-            params: vec![Type::Color]
-
-        }, Type::Int),
-
-        (FuncSign{
-            name: String::from("blue"),
-            params: vec![Type::Color]
-
-        }, Type::Int),
-
-        (FuncSign{
-            name: String::from("alpha"),
-            params: vec![Type::Color]
-
-        }, Type::Int),
-
-        (FuncSign{
-            name: String::from("rgba"),
-            params: vec![Type::Int, Type::Int, Type::Int, Type::Int]
-        }, Type::Color),
-
-        (FuncSign{
-            name: String::from("rgb"),
-            params: vec![Type::Int, Type::Int, Type::Int]
-        }, Type::Color),
-
-        (FuncSign{
-            name: String::from("cos"),
-            params: vec![Type::Float]
-        }, Type::Float),
-
-        (FuncSign{
-            name: String::from("sin"),
-            params: vec![Type::Float]
-        }, Type::Float),
-
-        (FuncSign{
-            name: String::from("floor"),
-            params: vec![Type::Float]
-        }, Type::Int),
-
-        (FuncSign{
-            name: String::from("ceil"),
-            params: vec![Type::Float]
-        }, Type::Int),
-
-        (FuncSign{
-            name: String::from("resize"),
-            params: vec![Type::Int, Type::Int]
-        }, Type::Void)
-    ])
+    BUILTINS.iter().map(|e| (FuncSign{ name: e.name.to_string(), params: e.params.to_vec() }, e.ret)).collect()
 }
 
 impl Default for Environment{
@@ -156,6 +66,7 @@ impl Default for Environment{
         env.push_type("float");
         env.push_type("bool");
         env.push_type("color");
+        env.push_type("string");
         env.func_signs = builtin_funcs().iter().map(|e| e.0.clone()).collect();
         env.func_returns = builtin_funcs();
         
@@ -171,8 +82,21 @@ impl Environment{
             func_signs: HashSet::new(), func_returns: HashMap::new(),
             imports: HashMap::new(),
             public_functions: HashSet::new(),
-            cached_imports: HashMap::new()
+            cached_imports: HashMap::new(),
+            host: Host::default()
+        }
+    }
+
+    /// Default environment of a script compiled for `host`, its native functions being callable
+    pub fn with_host(host:Host) -> Self{
+        let mut env = Self{ host, ..Self::default() };
+        let natives = env.host.natives.clone();
+
+        for (sign, ret) in natives.signatures(){
+            env.push_func_sign(sign.clone(), ret);
         }
+
+        env
     }
 
     pub fn add_ctx(&mut self, ctx:&str){
@@ -207,6 +131,15 @@ impl Environment{
         self.variables.contains_key(name)
     }
 
+    /// Removes every variable, to check a function declared where some are visible
+    pub fn take_vars(&mut self) -> HashMap<String, (Type, Option<String>)>{
+        std::mem::take(&mut self.variables)
+    }
+
+    pub fn set_vars(&mut self, variables:HashMap<String, (Type, Option<String>)>){
+        self.variables = variables;
+    }
+
     pub fn push_func_sign(&mut self, func_sign:FuncSign, return_type: Type){
         self.func_signs.insert(func_sign.clone());
         self.func_returns.insert(func_sign, return_type);
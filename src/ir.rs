@@ -1,8 +1,8 @@
-use std::{collections::HashMap, path::{PathBuf, Path}};
+use std::{cell::RefCell, collections::HashMap, fmt::Display, path::{PathBuf, Path}, rc::Rc};
 
 use neoglot_lib::{parser, lexer::Token};
 
-use crate::{TokenType, environment::{Type, FuncSign, builtin_funcs}, validator::get_type};
+use crate::{TokenType, environment::{Type, FuncSign, builtin_funcs}, validator::get_type, engine::Host, native::Natives, source::SourceProvider, vm::builtins::{self, BUILTINS}};
 
 type AST = parser::AST<Token<TokenType>>;
 
@@ -30,6 +30,7 @@ pub enum Instruction{
     Subf(Param, Param, String),
     Mulf(Param, Param, String),
     Divf(Param, Param, String),
+    Modf(Param, Param, String),
     Powf(Param, Param, String),
 
     Neg(Param, String),
@@ -55,39 +56,25 @@ pub enum Instruction{
     Not(Param, String),
 
     Flt(Param, String),
-    Int(Param, String),
     
-    Push(Param, Param),
-    Merge(Param, Param),
-    Put(Param, Param, Param),
-    Fill(Param),
-    Pop,
-    Save,
-    Sample(Param, Param, String),
-    Width(String),
-    Height(String),
-    Resize(Param, Param),
+    Subcanvas(Param, Param, Param, Param, bool),
+    Merge(Param, Param, bool),
 
     //JT(Param, String),
     JF(Param, String),
     
     Label(String),
+    /// Start of the statement at that line of the script, where a debugger can stop
+    Line(usize),
 
     Jump(String),
 
     Call(String, Vec<Param>),
-    Ret,
-
-    Red(Param, String),
-    Green(Param, String),
-    Blue(Param, String),
-    Alpha(Param, String),
-    RGBA(Param, Param, Param, Param, String),
-
-    Cos(Param, String),
-    Sin(Param, String),
-    Floor(Param, String),
-    Ceil(Param, String)
+    /// Calls the builtin at that index of `BUILTINS`, its result going to the register
+    Builtin(usize, Vec<Param>, String),
+    /// Calls the native function of that signature, its result going to the register
+    Native(FuncSign, Vec<Param>, String),
+    Ret
 }
 
 #[derive(Debug, Clone)]
@@ -100,7 +87,10 @@ struct Context{
     top_function: String,
     imports: Vec<Script>,
     pub func_labels: HashMap<FuncSign, String>,
-    path_aliases: HashMap<String, PathBuf>
+    path_aliases: HashMap<String, PathBuf>,
+    strings: Rc<RefCell<Vec<String>>>,
+    host: Host,
+    functions: Vec<Function>
 }
 
 impl Default for Context{
@@ -114,12 +104,24 @@ impl Default for Context{
             top_function: String::default(),
             imports: vec![],
             func_labels: HashMap::new(),
-            path_aliases: HashMap::new()
+            path_aliases: HashMap::new(),
+            strings: Rc::default(),
+            host: Host::default(),
+            functions: vec![]
         }
     }
 }
 
 impl Context{
+    /// Context of a script compiled for `host`, its native functions being callable
+    fn with_host(host:Host) -> Self{
+        let mut ctx = Context{ host, ..Context::default() };
+        for (sign, ret) in ctx.host.natives.signatures(){
+            ctx.func_returns.insert(sign.clone(), ret);
+        }
+        ctx
+    }
+
     pub fn add_register(&mut self, name:String, _type: Option<Type>){
         self.registers.push(name.clone());
         if let Some(t) = _type{
@@ -162,27 +164,79 @@ impl Context{
         self.imports.iter().any(|e| &e.path == path)
     }
 
+    /// Index of `content` in the string pool shared by every script of the runtime
+    pub fn intern(&self, content:&str) -> u32{
+        let mut strings = self.strings.borrow_mut();
+
+        match strings.iter().position(|e| e == content){
+            Some(i) => i as u32,
+            None => {
+                strings.push(content.to_string());
+                strings.len() as u32 - 1
+            }
+        }
+    }
+
 
 }
 
 #[derive(Debug, Clone)]
 pub struct Script{
     pub path: PathBuf,
-    pub program: Vec<Instruction>
+    pub program: Vec<Instruction>,
+    /// Functions defined at the top level of the script
+    pub functions: Vec<Function>
+}
+
+/// A function defined at the top level of a script
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function{
+    pub name: String,
+    /// Parameters in order, along with their name in the script
+    pub params: Vec<(String, Type)>,
+    pub ret: Type,
+    /// Declared with `pub`
+    pub public: bool,
+    pub(crate) label: String,
+    /// Variables of the body and parameters, along with the register holding them
+    pub(crate) locals: Vec<(String, String, Type)>
+}
+
+impl Function{
+    pub fn sign(&self) -> FuncSign{
+        FuncSign{ name: self.name.clone(), params: self.params.iter().map(|e| e.1).collect() }
+    }
+}
+
+/// Written the way the function is declared, such as `main(w:int, h:int)`
+impl Display for Function{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let params = self.params.iter().map(|(name, t)| format!("{name}:{t}")).collect::<Vec<String>>();
+        write!(f, "{}({})", self.name, params.join(", "))?;
+
+        if self.ret != Type::Void{
+            write!(f, " : {}", self.ret)?;
+        }
+        Ok(())
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Runtime{
-    pub scripts: Vec<Script>
+    pub scripts: Vec<Script>,
+    pub strings: Vec<String>,
+    pub natives: Rc<Natives>,
+    /// Where the files loaded while running, such as fonts, are read from
+    pub sources: Rc<dyn SourceProvider>
 }
 
-pub fn parse(forest: &Vec<AST>) -> Runtime{
+pub fn parse(forest: &Vec<AST>, host: &Host) -> Runtime{
     let path = Path::new(&forest[0].kind.location.file).to_path_buf();
-    let mut ctx = Context::default();
+    let mut ctx = Context::with_host(host.clone());
 
     let program = _parse(forest, &mut ctx);
 
-    let main_file = Script{path, program};
+    let main_file = Script{path, program, functions: ctx.functions};
     let mut scripts = vec![main_file];
 
     for imported_script in ctx.imports{
@@ -192,15 +246,86 @@ pub fn parse(forest: &Vec<AST>) -> Runtime{
     }
 
 
-    Runtime { scripts}
+    let strings = ctx.strings.take();
+
+    Runtime { scripts, strings, natives: ctx.host.natives.clone(), sources: ctx.host.sources.clone() }
 }
 
 
+/// Lowers the inputs of a REPL session one after the other, each one seeing what the previous ones declared
+#[derive(Debug, Clone)]
+pub(crate) struct Lowering{
+    ctx: Context,
+    /// Number of imported scripts already given back
+    imported: usize
+}
+
+impl Lowering{
+    pub fn new(host:&Host) -> Self{
+        Lowering{ ctx: Context::with_host(host.clone()), imported: 0 }
+    }
+
+    /// Lowers `forest`, interning its strings in `strings`.
+    /// Returns the code of its statements, the code of the functions it declares and the scripts it imports
+    pub fn lower(&mut self, forest:&[AST], strings:&mut Vec<String>) -> (Vec<Instruction>, Vec<Instruction>, Vec<Script>){
+        *self.ctx.strings.borrow_mut() = std::mem::take(strings);
+
+        let mut code = vec![];
+        let mut defs = vec![];
+
+        for tree in forest{
+            let mut instr = _parse(&vec![tree.clone()], &mut self.ctx);
+
+            if tree.kind.kind == TokenType::Def || tree.kind.kind == TokenType::Pub{
+                defs.append(&mut instr);
+            }else{
+                code.append(&mut instr);
+            }
+        }
+
+        let imports = self.ctx.imports[self.imported..].to_vec();
+        self.imported = self.ctx.imports.len();
+
+        *strings = self.ctx.strings.take();
+        (code, defs, imports)
+    }
+
+    /// Lowers the expression `expr`, its value going to `_rt`
+    pub fn lower_expr(&mut self, expr:&AST, strings:&mut Vec<String>) -> (Vec<Instruction>, Type){
+        *self.ctx.strings.borrow_mut() = std::mem::take(strings);
+
+        let lowered = if expr.children.is_empty(){
+            let (p, t) = to_param(&expr.kind, &self.ctx);
+            (vec![Instruction::Copy(p, "_rt".to_string())], t)
+        }else{
+            expand_expr(expr, &mut self.ctx, "_rt".to_string())
+        };
+
+        *strings = self.ctx.strings.take();
+        lowered
+    }
+
+    /// Goes back to what was declared in `before`.
+    /// The labels and registers created since are kept, the code using them may still be in the program
+    pub fn rollback(&mut self, before:Lowering){
+        let labels = std::mem::take(&mut self.ctx.labels);
+        let registers = std::mem::take(&mut self.ctx.registers);
+
+        *self = before;
+        self.ctx.labels = labels;
+        self.ctx.registers = registers;
+    }
+}
+
 fn _parse(forest: &Vec<AST>, ctx: &mut Context) -> Vec<Instruction>{
     let mut instructions = vec![];
 
 
     for tree in forest{
+        if ![TokenType::Def, TokenType::Pub, TokenType::Import].contains(&tree.kind.kind){
+            instructions.push(Instruction::Line(tree.kind.location.line));
+        }
+
         if tree.kind.kind == TokenType::Colon{
             add_var_in_context(&tree, ctx);
         
@@ -208,7 +333,7 @@ fn _parse(forest: &Vec<AST>, ctx: &mut Context) -> Vec<Instruction>{
             instructions.append(&mut parse_assign(tree, ctx));
         
         }else if tree.kind.kind == TokenType::Def{
-            instructions.append(&mut parse_def(tree, ctx));
+            instructions.append(&mut parse_def(tree, false, ctx));
         
         }else if tree.kind.kind == TokenType::Return{
             instructions.append(&mut parse_return(tree, ctx));
@@ -229,7 +354,7 @@ fn _parse(forest: &Vec<AST>, ctx: &mut Context) -> Vec<Instruction>{
             instructions.append(&mut parse_subcanvas(tree, ctx));
         
         }else if tree.kind.kind == TokenType::Pub{
-            instructions.append(&mut parse_def(&tree.children[0], ctx));
+            instructions.append(&mut parse_def(&tree.children[0], true, ctx));
         
         }else if tree.kind.kind == TokenType::Import{
             let self_path = Path::new(&tree.kind.location.file);
@@ -249,11 +374,11 @@ fn _parse(forest: &Vec<AST>, ctx: &mut Context) -> Vec<Instruction>{
                 Path::new(&content).to_path_buf()
             };
 
-            let forest = crate::prepare(path.to_str().unwrap());
-            let mut import_ctx = Context::default();
+            let forest = crate::prepare(path.to_str().unwrap(), &ctx.host);
+            let mut import_ctx = Context{ strings: ctx.strings.clone(), ..Context::with_host(ctx.host.clone()) };
             let program = _parse(&forest, &mut import_ctx);
 
-            let script = Script{path: path.clone(), program};
+            let script = Script{path: path.clone(), program, functions: import_ctx.functions};
 
             if !ctx.has_script(&path){
                 ctx.imports.push(script);
@@ -330,6 +455,10 @@ fn to_param(token: &Token<TokenType>, ctx: &Context) -> (Param, Type){
             Param::Value(u32::from_str_radix(&lit[1..], 16).expect("Unable to parse to u64")),
             Type::Color
         )
+    }else if token.kind == TokenType::String{
+        let lit = &token.literal;
+        (Param::Value(ctx.intern(&lit[1..lit.len()-1])), Type::String)
+
     }else{
         panic!("Should not be there")
     }
@@ -534,9 +663,31 @@ fn expand_binary_expr(expr: &AST, ctx: &mut Context, return_reg:String) -> (Vec<
         }
 
     }else if expr.kind.kind == TokenType::Mod{
-        instructions.push(Instruction::Mod(args.0, args.1, args.2));
-        Type::Int
-    
+        if left_type == Type::Float && right_type == Type::Float{
+            instructions.push(Instruction::Modf(args.0, args.1, args.2));
+            Type::Float
+
+        }else if left_type == Type::Int && right_type ==Type::Int{
+            instructions.push(Instruction::Mod(args.0, args.1, args.2));
+            Type::Int
+
+        }else if left_type == Type::Int && right_type == Type::Float{
+            let reg = String::from("_rt");
+            ctx.bindings.insert(reg.clone(), Type::Float);
+
+            instructions.push(Instruction::Flt(args.0, reg.clone()));
+            instructions.push(Instruction::Modf(Param::Register(reg), args.1, args.2));
+            Type::Float
+
+        }else{
+            let reg = String::from("_rt");
+            ctx.bindings.insert(reg.clone(), Type::Float);
+
+            instructions.push(Instruction::Flt(args.1, reg.clone()));
+            instructions.push(Instruction::Modf(args.0, Param::Register(reg), args.2));
+            Type::Float
+        }
+
     }else if expr.kind.kind == TokenType::GT{
         if left_type == Type::Int && right_type == Type::Int{
             instructions.push(Instruction::GT(args.0, args.1, args.2));
@@ -694,7 +845,7 @@ fn parse_assign(assign_tree: &AST, ctx: &mut Context) -> Vec<Instruction>{
     instructions
 }
 
-fn parse_def(def_tree: &AST, parent:&mut Context) -> Vec<Instruction>{
+fn parse_def(def_tree: &AST, is_public:bool, parent:&mut Context) -> Vec<Instruction>{
     let mut instructions = vec![];
     let func_tree = &def_tree.children[0];
     let block = &def_tree.children[def_tree.children.len()-1];
@@ -714,25 +865,45 @@ fn parse_def(def_tree: &AST, parent:&mut Context) -> Vec<Instruction>{
     instructions.push(Instruction::Label(parent.labels.last().unwrap().clone()));
 
     let mut params = vec![];
+    let mut names = vec![];
 
     for (i, param) in func_tree.children.iter().enumerate(){
         let r = format!("p{i}");
         let t = get_type(param.children[1].kind.literal.clone());
         params.push(t.unwrap());
+        names.push(param.children[0].kind.literal.clone());
         ctx.add_register(r.clone(), t);
         ctx.renamed_vars.insert(param.children[0].kind.literal.clone(), r);
     }
 
+    let label = parent.labels.last().unwrap().clone();
+    parent.functions.push(Function{
+        name: func_tree.kind.literal.clone(),
+        params: names.into_iter().zip(params.iter().copied()).collect(),
+        ret: ret_type,
+        public: is_public,
+        label: label.clone(),
+        locals: vec![]
+    });
+
     let sign = FuncSign{name: func_tree.kind.literal.clone(), params};
 
-    parent.func_labels.insert(sign.clone(), parent.labels.last().unwrap().clone());
+    parent.func_labels.insert(sign.clone(), label);
     parent.func_returns.insert(sign, ret_type);
     
     ctx.path_aliases = parent.path_aliases.clone();
+    ctx.strings = parent.strings.clone();
+    ctx.host = parent.host.clone();
     ctx.func_labels = parent.func_labels.clone();
     ctx.func_returns = parent.func_returns.clone();
 
     instructions.append(&mut _parse(&block.children, &mut ctx));
+
+    let mut locals = ctx.renamed_vars.iter()
+        .filter_map(|(name, reg)| Some((name.clone(), reg.clone(), *ctx.bindings.get(reg)?)))
+        .collect::<Vec<(String, String, Type)>>();
+    locals.sort_by_key(|e| ctx.registers.iter().position(|r| *r == e.1));
+    parent.functions.last_mut().unwrap().locals = locals;
     
     if ret_type == Type::Void{
         instructions.push(Instruction::Ret);
@@ -797,108 +968,19 @@ fn parse_func_call(func_call_tree: &AST, script_name:Option<String>,ctx: &mut Co
         FuncSign{name: name.clone(), params: params_type}
     };
 
-    if &name == "create_canvas"{
-        instructions.push(Instruction::Push(params[0].clone(), params[1].clone()));
-    }else if &name == "put"{
-        instructions.push(Instruction::Put(params[0].clone(), params[1].clone(), params[2].clone()));
-
-    }else if &name == "fill"{
-        instructions.push(Instruction::Fill(params[0].clone()));
-
-    }else if &name == "save_canvas"{
-        instructions.push(Instruction::Save);
-        instructions.push(Instruction::Pop);
-    }else if &name == "float"{
-        let reg = String::from("_rt");
-        ctx.bindings.insert(reg.clone(), Type::Float);
-
-        instructions.push(Instruction::Flt(params[0].clone(), reg));
-    
-    }else if &name == "int"{
-        let reg = String::from("_rt");
-        ctx.bindings.insert(reg.clone(), Type::Float);
-
-        instructions.push(Instruction::Int(params[0].clone(), reg));
-    }else if &name == "sample"{
-        let reg = String::from("_rt");
-        ctx.bindings.insert(reg.clone(), Type::Color);
-
-        instructions.push(Instruction::Sample(params[0].clone(), params[1].clone(), reg));
-
-    }else if &name == "width"{
-        let reg = String::from("_rt");
-        ctx.bindings.insert(reg.clone(), Type::Int);
-
-        instructions.push(Instruction::Width(reg));
-
-    }else if &name == "height"{
-        let reg = String::from("_rt");
-        ctx.bindings.insert(reg.clone(), Type::Int);
-
-        instructions.push(Instruction::Height(reg));
-
-    }else if &name == "red"{
-        let reg = String::from("_rt");
-        ctx.bindings.insert(reg.clone(), Type::Int);
-
-        instructions.push(Instruction::Red(params[0].clone(), reg));
-
-    }else if &name == "green"{
+    if let Some((ret, _)) = ctx.host.natives.get(&sign).filter(|_| script_name.is_none()){
         let reg = String::from("_rt");
-        ctx.bindings.insert(reg.clone(), Type::Int);
-
-        instructions.push(Instruction::Green(params[0].clone(), reg));
-
-    }else if &name == "blue"{
-        let reg = String::from("_rt");
-        ctx.bindings.insert(reg.clone(), Type::Int);
-
-        instructions.push(Instruction::Blue(params[0].clone(), reg));
+        ctx.bindings.insert(reg.clone(), ret);
 
-    }else if &name == "alpha"{
-        let reg = String::from("_rt");
-        ctx.bindings.insert(reg.clone(), Type::Int);
-
-        instructions.push(Instruction::Alpha(params[0].clone(), reg));
-
-    }else if &name == "rgba"{
-        let reg = String::from("_rt");
-        ctx.bindings.insert(reg.clone(), Type::Color);
-
-        instructions.push(Instruction::RGBA(params[0].clone(), params[1].clone(), params[2].clone(), params[3].clone(), reg));
-
-    }else if &name == "rgb"{
-        let reg = String::from("_rt");
-        ctx.bindings.insert(reg.clone(), Type::Color);
+        instructions.push(Instruction::Native(sign.clone(), params, reg));
 
-        instructions.push(Instruction::RGBA(params[0].clone(), params[1].clone(), params[2].clone(), Param::Value(255), reg));
-
-    }else if &name == "cos"{
-        let reg = String::from("_rt");
-        ctx.bindings.insert(reg.clone(), Type::Float);
-
-        instructions.push(Instruction::Cos(params[0].clone(), reg));
-
-    }else if &name == "sin"{
-        let reg = String::from("_rt");
-        ctx.bindings.insert(reg.clone(), Type::Float);
-
-        instructions.push(Instruction::Sin(params[0].clone(), reg));
-
-        
-    }else if &name == "floor"{
-        let reg = String::from("_rt");
-        ctx.bindings.insert(reg.clone(), Type::Int);
-
-        instructions.push(Instruction::Floor(params[0].clone(), reg));
-
-    }else if &name == "ceil"{
-        let reg = String::from("_rt");
-        ctx.bindings.insert(reg.clone(), Type::Int);
+    }else if let Some(index) = builtins::find(&sign).filter(|_| script_name.is_none()){
+        let ret = BUILTINS[index].ret;
+        if ret != Type::Void{
+            ctx.bindings.insert(String::from("_rt"), ret);
+        }
 
-        instructions.push(Instruction::Ceil(params[0].clone(), reg));
-    }else if &name == "resize"{
-        instructions.push(Instruction::Resize(params[0].clone(), params[1].clone()));
+        instructions.push(Instruction::Builtin(index, params, String::from("_rt")));
 
     }else{
         let unique_name = ctx.func_labels.get(&sign).unwrap().clone();
@@ -1063,9 +1145,11 @@ fn parse_subcanvas(subcanvas_tree: &AST, ctx: &mut Context) -> Vec<Instruction>{
         Param::Register(reg)
     };
 
-    instructions.push(Instruction::Push(width_param, height_param));
+    let copy = subcanvas_tree.kind.literal == "subcanvas_copy";
+
+    instructions.push(Instruction::Subcanvas(x_param.clone(), y_param.clone(), width_param, height_param, copy));
     instructions.append(&mut _parse(&block.children, ctx));
-    instructions.push(Instruction::Merge(x_param, y_param));
+    instructions.push(Instruction::Merge(x_param, y_param, copy));
 
     instructions
 }
\ No newline at end of file
@@ -0,0 +1,356 @@
+use environment::Environment;
+use ir::Runtime;
+use neoglot_lib::{regex::*, lexer::*, parser::AST};
+use validator::verify;
+use engine::{Host, report_message};
+use std::path::Path;
+
+mod parser;
+mod validator;
+mod environment;
+mod ir;
+mod vm;
+mod noise;
+mod draw;
+mod blend;
+mod text;
+mod color;
+mod pixels;
+mod engine;
+mod source;
+mod native;
+mod session;
+pub mod ora;
+pub mod palette;
+pub mod dap;
+
+pub use engine::{Engine, Program, Value, Diagnostic, Diagnostics, RuntimeError};
+pub use environment::{FuncSign, Type};
+pub use ir::Function;
+pub use native::{Natives, NativeFn};
+pub use session::{Session, EvalError};
+pub use source::{SourceProvider, FileSystem, MemorySources};
+pub use vm::{Canvas, Layer};
+pub use vm::debug::{Debugger, Paused, Frame, Stop, Resume};
+pub use pixels::{Pixels, Depth};
+pub use blend::BlendMode;
+
+#[derive(Debug, Hash, PartialOrd, PartialEq, Eq, Copy, Clone)]
+pub(crate) enum TokenType{
+    Ident,
+
+    Pub, Import, As,
+
+    String,
+
+    Int, Float, Hex, Bool,
+
+    LParen, RParen,
+    LBracket, RBracket,
+
+    Comma, Colon, SemiColon, Dot,
+
+    If, Else, While,
+    Travel, Subcanvas,
+    Def, Return,
+
+    Plus, Minus, Mul,
+    Div, Mod, Pow,
+
+    Eq,
+
+    And, Or,
+    DoubleEq, GT, LT,
+    Not, NotEq, GTEq,
+    LTEq,
+
+    SingleComment
+}
+
+impl Symbol for TokenType{}
+impl TokenKind for TokenType{}
+
+fn to_rgba(pixel: u32) -> [u8; 4]{
+    let r = ((pixel >> 24) & 0xff) as u8;
+    let g = ((pixel >> 16) & 0xff) as u8;
+    let b = ((pixel >> 8) & 0xff) as u8;
+    let a = (pixel & 0xff) as u8;
+    [r, g, b, a]
+}
+
+fn from_rgba(r: u8, g: u8, b: u8, a: u8) -> u32{
+    let r = (r as u32 & 0xff) << 24;
+    let g = (g as u32 & 0xff) << 16;
+    let b = (b as u32 & 0xff) << 8;
+    let a = a as u32 & 0xff;
+
+    let color = r | g | b | a;
+    color
+
+}
+
+fn init_lexer(lexer:&mut Lexer<TokenType>){
+    let ident_regex = Regex::new()
+        .then(RegexElement::AnyOf(vec![
+            RegexElement::Set('a', 'z', Quantifier::Exactly(1)),
+            RegexElement::Set('A', 'Z', Quantifier::Exactly(1))
+        ]))
+        .then(RegexElement::Group(vec![
+            RegexElement::AnyOf(vec![
+                RegexElement::Item('_', Quantifier::Exactly(1)),
+                RegexElement::Set('a', 'z', Quantifier::Exactly(1)),
+                RegexElement::Set('A', 'Z', Quantifier::Exactly(1)),
+                RegexElement::Set('0', '9', Quantifier::Exactly(1))
+            ])
+        ], Quantifier::ZeroOrMany));
+
+    let float_regex = Regex::new()
+        .then(RegexElement::Item('-', Quantifier::ZeroOrOne))
+        .then(RegexElement::Set('0', '9', Quantifier::OneOrMany))
+        .then(RegexElement::Item('.', Quantifier::Exactly(1)))
+        .then(RegexElement::Set('0', '9', Quantifier::OneOrMany));
+
+    let int_regex = Regex::new()
+        .then(RegexElement::Item('-', Quantifier::ZeroOrOne))
+        .then(RegexElement::Set('0', '9', Quantifier::OneOrMany));
+
+    let hex_regex = Regex::new()
+        .then(RegexElement::Item('#', Quantifier::Exactly(1)))
+        .then(RegexElement::Group(vec![
+            RegexElement::AnyOf(vec![
+                RegexElement::Set('0', '9', Quantifier::Exactly(1)),
+                RegexElement::Set('a', 'f', Quantifier::Exactly(1)),
+                RegexElement::Set('A', 'F', Quantifier::Exactly(1))
+            ])
+        ], Quantifier::Exactly(8)));
+
+    let if_regex = Regex::new()
+        .then(RegexElement::Item('i', Quantifier::Exactly(1)))
+        .then(RegexElement::Item('f', Quantifier::Exactly(1)));
+
+    let else_regex = Regex::new()
+        .then(RegexElement::Item('e', Quantifier::Exactly(1)))
+        .then(RegexElement::Item('l', Quantifier::Exactly(1)))
+        .then(RegexElement::Item('s', Quantifier::Exactly(1)))
+        .then(RegexElement::Item('e', Quantifier::Exactly(1)));
+
+    let while_regex = Regex::new()
+        .then(RegexElement::Item('w', Quantifier::Exactly(1)))
+        .then(RegexElement::Item('h', Quantifier::Exactly(1)))
+        .then(RegexElement::Item('i', Quantifier::Exactly(1)))
+        .then(RegexElement::Item('l', Quantifier::Exactly(1)))
+        .then(RegexElement::Item('e', Quantifier::Exactly(1)));
+
+    let travel_regex = Regex::new()
+        .then(RegexElement::Item('t', Quantifier::Exactly(1)))
+        .then(RegexElement::Item('r', Quantifier::Exactly(1)))
+        .then(RegexElement::Item('a', Quantifier::Exactly(1)))
+        .then(RegexElement::Item('v', Quantifier::Exactly(1)))
+        .then(RegexElement::Item('e', Quantifier::Exactly(1)))
+        .then(RegexElement::Item('l', Quantifier::Exactly(1)));
+
+    let subcanvas_regex = Regex::new()
+        .then(RegexElement::Item('s', Quantifier::Exactly(1)))
+        .then(RegexElement::Item('u', Quantifier::Exactly(1)))
+        .then(RegexElement::Item('b', Quantifier::Exactly(1)))
+        .then(RegexElement::Item('c', Quantifier::Exactly(1)))
+        .then(RegexElement::Item('a', Quantifier::Exactly(1)))
+        .then(RegexElement::Item('n', Quantifier::Exactly(1)))
+        .then(RegexElement::Item('v', Quantifier::Exactly(1)))
+        .then(RegexElement::Item('a', Quantifier::Exactly(1)))
+        .then(RegexElement::Item('s', Quantifier::Exactly(1)))
+        .then(RegexElement::Group(vec![
+            RegexElement::Item('_', Quantifier::Exactly(1)),
+            RegexElement::Item('c', Quantifier::Exactly(1)),
+            RegexElement::Item('o', Quantifier::Exactly(1)),
+            RegexElement::Item('p', Quantifier::Exactly(1)),
+            RegexElement::Item('y', Quantifier::Exactly(1))
+        ], Quantifier::ZeroOrOne));
+
+    let def_regex = Regex::new()
+        .then(RegexElement::Item('d', Quantifier::Exactly(1)))
+        .then(RegexElement::Item('e', Quantifier::Exactly(1)))
+        .then(RegexElement::Item('f', Quantifier::Exactly(1)));
+
+    let return_regex = Regex::new()
+        .then(RegexElement::Item('r', Quantifier::Exactly(1)))
+        .then(RegexElement::Item('e', Quantifier::Exactly(1)))
+        .then(RegexElement::Item('t', Quantifier::Exactly(1)))
+        .then(RegexElement::Item('u', Quantifier::Exactly(1)))
+        .then(RegexElement::Item('r', Quantifier::Exactly(1)))
+        .then(RegexElement::Item('n', Quantifier::Exactly(1)));
+
+    let bool_regex = Regex::new()
+        .then(RegexElement::AnyOf(vec![
+            RegexElement::Group(vec![
+                RegexElement::Item('t', Quantifier::Exactly(1)),
+                RegexElement::Item('r', Quantifier::Exactly(1)),
+                RegexElement::Item('u', Quantifier::Exactly(1)),
+                RegexElement::Item('e', Quantifier::Exactly(1))
+            ], Quantifier::Exactly(1)),
+
+            RegexElement::Group(vec![
+                RegexElement::Item('f', Quantifier::Exactly(1)),
+                RegexElement::Item('a', Quantifier::Exactly(1)),
+                RegexElement::Item('l', Quantifier::Exactly(1)),
+                RegexElement::Item('s', Quantifier::Exactly(1)),
+                RegexElement::Item('e', Quantifier::Exactly(1))
+            ], Quantifier::Exactly(1))
+        ]));
+
+    let gt_eq_regex = Regex::new()
+        .then(RegexElement::Item('>', Quantifier::Exactly(1)))
+        .then(RegexElement::Item('=', Quantifier::Exactly(1)));
+
+    let lt_eq_regex = Regex::new()
+        .then(RegexElement::Item('<', Quantifier::Exactly(1)))
+        .then(RegexElement::Item('=', Quantifier::Exactly(1)));
+    
+    let not_eq_regex = Regex::new()
+        .then(RegexElement::Item('!', Quantifier::Exactly(1)))
+        .then(RegexElement::Item('=', Quantifier::Exactly(1)));
+
+    let sigle_comment_regex = Regex::new()
+        .then(RegexElement::Item('/', Quantifier::Exactly(2)))
+        .then(RegexElement::NoneOf(vec![
+            RegexElement::Item('\n', Quantifier::Exactly(1))
+        ], Quantifier::ZeroOrMany))
+        .then(RegexElement::Item('\n', Quantifier::ZeroOrMany));
+
+    let pub_regex = Regex::new()
+            .then(RegexElement::Item('p', Quantifier::Exactly(1)))
+            .then(RegexElement::Item('u', Quantifier::Exactly(1)))
+            .then(RegexElement::Item('b', Quantifier::Exactly(1)));
+
+    let import_regex = Regex::new()
+            .then(RegexElement::Item('i', Quantifier::Exactly(1)))
+            .then(RegexElement::Item('m', Quantifier::Exactly(1)))
+            .then(RegexElement::Item('p', Quantifier::Exactly(1)))
+            .then(RegexElement::Item('o', Quantifier::Exactly(1)))
+            .then(RegexElement::Item('r', Quantifier::Exactly(1)))
+            .then(RegexElement::Item('t', Quantifier::Exactly(1)));
+
+    let as_regex = Regex::new()
+            .then(RegexElement::Item('a', Quantifier::Exactly(1)))
+            .then(RegexElement::Item('s', Quantifier::Exactly(1)));
+
+    let string_regex = Regex::new()
+            .then(RegexElement::Item('"', Quantifier::Exactly(1)))
+            .then(RegexElement::NoneOf(vec![
+                RegexElement::Item('"', Quantifier::Exactly(1))
+            ], Quantifier::ZeroOrMany))
+            .then(RegexElement::Item('"', Quantifier::Exactly(1)));
+
+
+    lexer.register(LexerNode::new(if_regex, TokenType::If));
+    lexer.register(LexerNode::new(else_regex, TokenType::Else));
+    lexer.register(LexerNode::new(while_regex, TokenType::While));
+    //lexer.register(LexerNode::new(travel_regex, TokenType::Travel));
+    lexer.register(LexerNode::new(subcanvas_regex, TokenType::Subcanvas));
+    lexer.register(LexerNode::new(def_regex, TokenType::Def));
+    lexer.register(LexerNode::new(return_regex, TokenType::Return));
+
+    lexer.register(LexerNode::new(bool_regex, TokenType::Bool));
+    lexer.register(LexerNode::new(float_regex, TokenType::Float));
+    lexer.register(LexerNode::new(int_regex, TokenType::Int));
+    lexer.register(LexerNode::new(hex_regex, TokenType::Hex));
+
+    lexer.register(LexerNode::new(pub_regex, TokenType::Pub));
+    lexer.register(LexerNode::new(import_regex, TokenType::Import));
+    lexer.register(LexerNode::new(as_regex, TokenType::As));
+    lexer.register(LexerNode::new(string_regex, TokenType::String));
+
+    lexer.register(LexerNode::new(ident_regex, TokenType::Ident));
+
+
+
+    lexer.register(LexerNode::new(Regex::new().then(RegexElement::Item('(', Quantifier::Exactly(1))), TokenType::LParen));
+    lexer.register(LexerNode::new(Regex::new().then(RegexElement::Item(')', Quantifier::Exactly(1))), TokenType::RParen));
+
+    lexer.register(LexerNode::new(Regex::new().then(RegexElement::Item('{', Quantifier::Exactly(1))), TokenType::LBracket));
+    lexer.register(LexerNode::new(Regex::new().then(RegexElement::Item('}', Quantifier::Exactly(1))), TokenType::RBracket));
+
+    lexer.register(LexerNode::new(Regex::new().then(RegexElement::Item(',', Quantifier::Exactly(1))), TokenType::Comma));
+    lexer.register(LexerNode::new(Regex::new().then(RegexElement::Item(':', Quantifier::Exactly(1))), TokenType::Colon));
+    lexer.register(LexerNode::new(Regex::new().then(RegexElement::Item(';', Quantifier::Exactly(1))), TokenType::SemiColon));
+    lexer.register(LexerNode::new(Regex::new().then(RegexElement::Item('.', Quantifier::Exactly(1))), TokenType::Dot));
+
+    lexer.register(LexerNode::new(Regex::new().then(RegexElement::Item('+', Quantifier::Exactly(1))), TokenType::Plus));
+    lexer.register(LexerNode::new(Regex::new().then(RegexElement::Item('-', Quantifier::Exactly(1))), TokenType::Minus));
+    lexer.register(LexerNode::new(Regex::new().then(RegexElement::Item('*', Quantifier::Exactly(1))), TokenType::Mul));
+    
+    lexer.register(LexerNode::new(sigle_comment_regex, TokenType::SingleComment));
+    lexer.register(LexerNode::new(Regex::new().then(RegexElement::Item('/', Quantifier::Exactly(1))), TokenType::Div));
+    lexer.register(LexerNode::new(Regex::new().then(RegexElement::Item('%', Quantifier::Exactly(1))), TokenType::Mod));
+    lexer.register(LexerNode::new(Regex::new().then(RegexElement::Item('^', Quantifier::Exactly(1))), TokenType::Pow));
+
+
+    lexer.register(LexerNode::new(Regex::new().then(RegexElement::Item('&', Quantifier::Exactly(2))), TokenType::And));
+    lexer.register(LexerNode::new(Regex::new().then(RegexElement::Item('|', Quantifier::Exactly(2))), TokenType::Or));
+
+    lexer.register(LexerNode::new(Regex::new().then(RegexElement::Item('=', Quantifier::Exactly(2))), TokenType::DoubleEq));
+    lexer.register(LexerNode::new(Regex::new().then(RegexElement::Item('=', Quantifier::Exactly(1))), TokenType::Eq));
+
+    lexer.register(LexerNode::new(gt_eq_regex, TokenType::GTEq));
+    lexer.register(LexerNode::new(lt_eq_regex, TokenType::LTEq));
+    lexer.register(LexerNode::new(not_eq_regex, TokenType::NotEq));
+
+    lexer.register(LexerNode::new(Regex::new().then(RegexElement::Item('>', Quantifier::Exactly(1))), TokenType::GT));
+    lexer.register(LexerNode::new(Regex::new().then(RegexElement::Item('<', Quantifier::Exactly(1))), TokenType::LT));
+    lexer.register(LexerNode::new(Regex::new().then(RegexElement::Item('!', Quantifier::Exactly(1))), TokenType::Not));
+
+    
+}
+
+
+pub(crate) fn tokenize(content: &str, path: &str) -> LexingResult<TokenType>{
+    let mut lexer = Lexer::new();
+    init_lexer(&mut lexer);
+
+    lexer.tokenize_content(content, path)
+}
+
+pub(crate) fn prepare(path: &str, host: &Host) -> Vec<AST<Token<TokenType>>>{
+    let content = match host.sources.read(Path::new(path)){
+        Ok(content) => content,
+        Err(e) => {
+            report_message(&e);
+            return vec![];
+        }
+    };
+
+    match tokenize(&content, path){
+        LexingResult::Ok(tokens) => {
+            match parser::parse(&tokens, true){
+                Some(forest) => {
+                    let mut env = Environment::with_host(host.clone());
+                    if verify(&forest, None, &mut env){
+                        forest
+                    }else {vec![]}
+                },
+
+                None => {
+                    report_message(&format!("Could not parse {path}"));
+                    vec![]
+                }
+            }
+        },
+
+        LexingResult::Err(errs) => {
+            for e in errs{
+                report_message(&e.to_string());
+            }
+            vec![]
+        }
+    }
+}
+
+fn parse(path: &str, host: &Host) -> Option<Runtime>{
+    let forest = prepare(path, host);
+    if forest.is_empty(){
+        return None;
+    }
+
+    Some(ir::parse(&forest, host))
+    
+}
@@ -1,63 +1,48 @@
-use environment::Environment;
-use image::ImageFormat;
-use ir::Runtime;
-use neoglot_lib::{regex::*, lexer::*, parser::AST};
-use validator::verify;
-use vm::VM;
-use std::{env, fmt::Display, collections::HashSet, path::Path};
+use papyrus::{Engine, Program, Session, EvalError, Canvas, Function, Value, Debugger, Paused, Stop, Resume, ora, palette::{Dither, Indexed, Palette, PaletteSource}};
+use std::{env, fs, process, thread, io::{self, Write}, fmt::Display, collections::{HashMap, HashSet}, path::{Path, PathBuf}, time::{Duration, SystemTime}};
 
-mod parser;
-mod validator;
-mod environment;
-mod ir;
-mod vm;
-
-#[derive(Debug, Hash, PartialOrd, PartialEq, Eq, Copy, Clone)]
-pub enum TokenType{
-    Ident,
-
-    Pub, Import, As,
-
-    String,
-
-    Int, Float, Hex, Bool,
-
-    LParen, RParen,
-    LBracket, RBracket,
+const IMG_OUTPUT:&str = "-img";
+const VID_OUTPUT:&str = "-vid";
 
-    Comma, Colon, SemiColon, Dot,
+const IMG_FORMAT:&[&str] = &["png", "jpg", "exr", "hdr", "gif"];
+const VID_FORMAT:&[&str] = &["mp4"];
 
-    If, Else, While,
-    Travel, Subcanvas,
-    Def, Return,
+const SEED_OPTION:&str = "--seed";
+const EXPORT_LAYERS_OPTION:&str = "--export-layers";
+const PALETTE_OPTION:&str = "--palette";
+const DITHER_OPTION:&str = "--dither";
+const ARG_OPTION:&str = "--arg";
+const ENTRY_OPTION:&str = "--entry";
+const PREVIEW_OPTION:&str = "--preview";
 
-    Plus, Minus, Mul,
-    Div, Mod, Pow,
+/// Time between two checks of the watched scripts
+const WATCH_INTERVAL:Duration = Duration::from_millis(250);
 
-    Eq,
+/// Function run when no other is given with `--entry`
+const DEFAULT_ENTRY:&str = "main";
 
-    And, Or,
-    DoubleEq, GT, LT,
-    Not, NotEq, GTEq,
-    LTEq,
+/// Marks the start of the arguments given to `main`
+const ARGS_SEPARATOR:&str = "--";
 
-    SingleComment
-}
+const LAYER_FORMAT:&[&str] = &["png", "ora"];
 
-impl Symbol for TokenType{}
-impl TokenKind for TokenType{}
-
-const IMG_OUTPUT:&str = "-img";
-const VID_OUTPUT:&str = "-vid";
-
-const IMG_FORMAT:&[&str] = &["png", "jpg"];
-const VID_FORMAT:&[&str] = &["mp4"];
+/// Options that consume the argument following them
+const VALUED_OPTIONS:&[&str] = &[SEED_OPTION, EXPORT_LAYERS_OPTION, PALETTE_OPTION, DITHER_OPTION, ARG_OPTION, ENTRY_OPTION, PREVIEW_OPTION];
 
 #[derive(Debug)]
 struct Command<'a>{
     name: &'a str,
     args: Vec<String>,
-    options: HashSet<String>
+    options: HashSet<String>,
+    values: HashMap<String, Vec<String>>,
+    /// Arguments following `--`
+    script_args: Vec<String>
+}
+
+impl Command<'_>{
+    fn value(&self, option:&str) -> Option<&str>{
+        self.values.get(option).and_then(|e| e.last()).map(|e| e.as_str())
+    }
 }
 
 fn main() {
@@ -71,10 +56,10 @@ fn main() {
     }
     let cmd = read_cmd(&args);
 
-    if cmd.name == "run"{
-        if cmd.args.len() == 1{
-            run(&cmd.args[0], IMG_OUTPUT, IMG_FORMAT[0], cmd.options);
-        
+    if (cmd.name == "run" || cmd.name == "watch") && (1..=3).contains(&cmd.args.len()){
+        let (output, format) = if cmd.args.len() == 1{
+            (IMG_OUTPUT, IMG_FORMAT[0])
+
         }else if cmd.args.len() == 2{
             let default_format = if &cmd.args[1] == IMG_OUTPUT{
                 IMG_FORMAT[0]
@@ -83,32 +68,64 @@ fn main() {
             }else{
                 eprintln!("Unknown output type: {}", cmd.args[1]);
                 help();
-                return;
+                process::exit(1);
             };
 
-            run(&cmd.args[0], &cmd.args[1], default_format, cmd.options)
-        }else if cmd.args.len() == 3{
+            (cmd.args[1].as_str(), default_format)
+        }else{
             if &cmd.args[1] != IMG_OUTPUT && &cmd.args[1] != VID_OUTPUT{
                 eprintln!("Unknown output type: {}", cmd.args[1]);
                 help();
-                return;
+                process::exit(1);
             }
             
             if &cmd.args[1] == IMG_OUTPUT{
                 if !IMG_FORMAT.contains(&cmd.args[2].as_str()){
                     eprintln!("Unknown image file format: {}", cmd.args[2]);
                     help();
-                    return;
+                    process::exit(1);
                 }
             }else if &cmd.args[1] == VID_OUTPUT{
                 if !VID_FORMAT.contains(&cmd.args[2].as_str()){
                     eprintln!("Unknown video file format: {}", cmd.args[2]);
                     help();
-                    return;
+                    process::exit(1);
                 }
             }
 
-            run(&cmd.args[0], &cmd.args[1], &cmd.args[2], cmd.options);
+            (cmd.args[1].as_str(), cmd.args[2].as_str())
+        };
+
+        if cmd.name == "watch"{
+            watch(&cmd.args[0], output, format, &cmd);
+        }else if run(&cmd.args[0], output, format, &cmd).is_err(){
+            process::exit(1);
+        }
+        return;
+    }
+
+    if cmd.name == "list-entries" && cmd.args.len() == 1{
+        if list_entries(&cmd.args[0]).is_err(){
+            process::exit(1);
+        }
+        return;
+    }
+
+    if cmd.name == "debug" && cmd.args.len() == 1{
+        if debug(&cmd.args[0], &cmd).is_err(){
+            process::exit(1);
+        }
+        return;
+    }
+
+    if cmd.name == "repl" && cmd.args.is_empty(){
+        repl(&cmd);
+        return;
+    }
+
+    if cmd.name == "dap" && cmd.args.is_empty(){
+        if let Err(e) = papyrus::dap::serve(io::stdin().lock(), io::stdout()){
+            eprintln!("Debug adapter stopped: {e}");
         }
         return;
     }
@@ -118,76 +135,614 @@ fn main() {
     
 }
 
-fn run(file:&str, output:&str, format:&str, options: HashSet<String>){
-    let base = Path::new(file);
-
-    let path = if base.is_relative(){
+/// Absolute path of a script given on the command line
+fn script_path(base:&Path) -> PathBuf{
+    if base.is_relative(){
         env::current_dir().unwrap().join(base)
     }else{
         base.to_path_buf()
-    };
+    }
+}
+
+/// Checks the script path given on the command line and compiles it, printing the errors.
+/// On failure, gives the scripts the errors come from
+fn compile(base:&Path) -> Result<Program, Vec<PathBuf>>{
+    let path = script_path(base);
 
 
     if let Some(ext) = path.extension(){
         if ext != "pprs"{
             eprintln!("Expected a '.pprs' file extension");
-            return;
+            return Err(vec![]);
         }
     }else{
         eprintln!("Expected a '.pprs' file extension");
-        return;
+        return Err(vec![]);
     }
 
     if !path.exists(){
         eprintln!("Could not find file {}", path.display());
-        return;
+        return Err(vec![]);
+    }
+
+    match Engine::new().compile(&path){
+        Ok(program) => Ok(program),
+        Err(diagnostics) => {
+            eprintln!("{diagnostics}");
+            Err(diagnostics.scripts)
+        }
     }
+}
 
-    if let Some(f) = path.to_str(){
-        let runtime = parse(f);
+fn list_entries(file:&str) -> Result<(), ()>{
+    let Ok(program) = compile(Path::new(file)) else {
+        return Err(());
+    };
 
-        if let Some(runtime) = runtime{
-            let mut vm = VM::new(runtime);
-            vm.run(&path, "main");
+    for entry in program.entries(){
+        println!("{entry}");
+    }
+    Ok(())
+}
 
-            if output == IMG_OUTPUT{
-                for (i, canvas) in vm.get_saved_canvas().iter().enumerate(){
+/// Runs the script and saves its canvases, gives the scripts it was compiled from.
+/// They are given as error when the script can't be compiled or run
+fn run(file:&str, output:&str, format:&str, cmd: &Command) -> Result<Vec<PathBuf>, Vec<PathBuf>>{
+    let base = Path::new(file);
 
-                    if format == IMG_FORMAT[0]{
-                        match canvas.data.save_with_format(&format!("canvas{i}.png"), ImageFormat::Png){
-                            Err(e) => {
-                                println!("Could not save image at canvas{i}.png:\n\t{e}");
-                            },
-                            _ => ()
-                        }
-                    
-                    }else if format == IMG_FORMAT[1]{
-                        match canvas.data.save_with_format(&format!("canvas{i}.jpg"), ImageFormat::Jpeg){
-                            Err(e) => {
-                                println!("Could not save image at canvas{i}.jpg:\n\t{e}");
-                            },
-
-                            _ => ()
+    let seed = match cmd.value(SEED_OPTION){
+        Some(s) => match s.parse::<i32>(){
+            Ok(seed) => Some(seed as u32),
+            Err(_) => {
+                eprintln!("Invalid seed: {s}");
+                return Err(vec![]);
+            }
+        },
+        None => None
+    };
+
+    let layer_format = cmd.value(EXPORT_LAYERS_OPTION);
+    if let Some(f) = layer_format{
+        if !LAYER_FORMAT.contains(&f){
+            eprintln!("Unknown layers file format: {f}");
+            help();
+            return Err(vec![]);
+        }
+    }
+
+    let palette = match cmd.value(PALETTE_OPTION){
+        Some(p) => match PaletteSource::parse(p){
+            Ok(palette) => Some(palette),
+            Err(e) => {
+                eprintln!("Invalid palette {p}: {e}");
+                return Err(vec![]);
+            }
+        },
+        None => None
+    };
+
+    if palette.is_some() && format != "png" && format != "gif"{
+        eprintln!("{PALETTE_OPTION} can only be used with the png and gif formats");
+        return Err(vec![]);
+    }
+
+    let dither = match cmd.value(DITHER_OPTION){
+        Some(d) => match Dither::from_name(d){
+            Some(dither) => dither,
+            None => {
+                eprintln!("Unknown dithering: {d}, expected one of: {}", Dither::NAMES.join(", "));
+                return Err(vec![]);
+            }
+        },
+        None => Dither::None
+    };
+
+    let mut program = compile(base)?;
+    if let Some(seed) = seed{
+        program.set_seed(seed);
+    }
+
+    let Some((entry, args)) = entry(&program, cmd) else {
+        return Err(program.scripts().to_vec());
+    };
+
+    let saved = match program.run_layers(&entry.name, &args){
+        Ok(saved) => saved,
+        Err(e) => {
+            eprintln!("Runtime error: {e}");
+            return Err(program.scripts().to_vec());
+        }
+    };
+
+    if output == IMG_OUTPUT{
+        for (i, (canvas, _)) in saved.iter().enumerate(){
+            let path = format!("canvas{i}.{format}");
+
+            // gif is always indexed, with 256 colors unless a palette is given
+            let result = if palette.is_some() || format == "gif"{
+                let image = canvas.data.to_rgba8();
+                let colors = palette.as_ref().map(|e| e.palette_for(&image)).unwrap_or_else(|| Palette::automatic(&image, 256));
+                let indexed = Indexed::new(&image, colors, dither);
+
+                if format == "gif" { indexed.save_gif(&path) } else { indexed.save_png(&path) }
+            }else{
+                canvas.data.save(&path, format)
+            };
+
+            if let Err(e) = result{
+                println!("Could not save image at {path}:\n\t{e}");
+            }
+        }
+
+        if let Some(layer_format) = layer_format{
+            export_layers(&saved, layer_format);
+        }
+    }
+
+    Ok(program.scripts().to_vec())
+}
+
+/// Runs the script again every time it or one of its imports changes, until the process is stopped
+fn watch(file:&str, output:&str, format:&str, cmd: &Command){
+    let path = script_path(Path::new(file));
+
+    loop{
+        let mut scripts = run(file, output, format, cmd).unwrap_or_else(|scripts| scripts);
+        if !scripts.contains(&path){
+            scripts.push(path.clone());
+        }
+
+        println!("Watching {} script(s) for changes, press Ctrl+C to stop", scripts.len());
+
+        let before = versions(&scripts);
+        while versions(&scripts) == before{
+            thread::sleep(WATCH_INTERVAL);
+        }
+
+        println!("Change detected, running {file} again");
+    }
+}
+
+/// Debugger reading its commands from the standard input
+struct Prompt{
+    /// Script debugged, the one of the breakpoints given by line only
+    main: PathBuf,
+    breakpoints: Vec<(PathBuf, usize)>,
+    /// Lines of the scripts already shown
+    sources: HashMap<PathBuf, Vec<String>>
+}
+
+impl Prompt{
+    /// Reads `<file>:<line>` or `<line>`, the file being relative to the working directory
+    fn location(&self, text:&str) -> Option<(PathBuf, usize)>{
+        match text.rsplit_once(':'){
+            Some((file, line)) => Some((script_path(Path::new(file)), line.parse().ok()?)),
+            None => Some((self.main.clone(), text.parse().ok()?))
+        }
+    }
+
+    /// Text of the line of a script, empty if it can't be read
+    fn source_line(&mut self, path:&Path, line:usize) -> &str{
+        let lines = self.sources.entry(path.to_path_buf())
+            .or_insert_with(|| fs::read_to_string(path).map(|e| e.lines().map(str::to_string).collect()).unwrap_or_default());
+
+        lines.get(line.wrapping_sub(1)).map(|e| e.trim()).unwrap_or_default()
+    }
+}
+
+impl Debugger for Prompt{
+    fn breakpoint(&self, path:&Path, line:usize) -> bool{
+        self.breakpoints.iter().any(|(p, l)| p == path && *l == line)
+    }
+
+    fn stopped(&mut self, paused:&Paused, reason:Stop) -> Resume{
+        let frame = &paused.frames[0];
+        match reason{
+            Stop::Entry => println!("Stopped at the start of {}", frame.function),
+            Stop::Breakpoint => println!("Stopped at a breakpoint in {}", frame.function),
+            Stop::Step => {},
+            Stop::Error(e) => println!("Runtime error in {}: {e}", frame.function)
+        }
+
+        let source = self.source_line(&frame.script, frame.line).to_string();
+        println!("{}:{}  {source}", display_path(&frame.script), frame.line);
+
+        loop{
+            print!("(debug) ");
+            io::stdout().flush().unwrap();
+
+            let mut line = String::new();
+            if io::stdin().read_line(&mut line).unwrap_or(0) == 0{
+                println!();
+                return Resume::Terminate;
+            }
+
+            let (name, arg) = line.trim().split_once(' ').map(|(n, a)| (n, a.trim())).unwrap_or((line.trim(), ""));
+            match name{
+                "continue" | "c" => return Resume::Continue,
+                "next" | "n" => return Resume::StepOver,
+                "step" | "s" => return Resume::StepInto,
+                "finish" => return Resume::StepOut,
+                "quit" | "q" => return Resume::Terminate,
+
+                "break" | "b" | "delete" | "d" => {
+                    let Some(location) = self.location(arg) else {
+                        eprintln!("Expected {name} <file>:<line> or {name} <line>");
+                        continue;
+                    };
+
+                    if matches!(name, "break" | "b"){
+                        if !self.breakpoints.contains(&location){
+                            self.breakpoints.push(location);
                         }
+                    }else if !self.breakpoints.contains(&location){
+                        eprintln!("No breakpoint at {}:{}", display_path(&location.0), location.1);
+                    }else{
+                        self.breakpoints.retain(|e| *e != location);
+                    }
+                },
+
+                "breakpoints" => {
+                    for (path, line) in &self.breakpoints{
+                        println!("{}:{line}", display_path(path));
+                    }
+                },
+
+                "locals" | "l" => {
+                    let i = arg.parse::<usize>().unwrap_or_default();
+                    let Some(frame) = paused.frames.get(i) else {
+                        eprintln!("No frame #{i}, see stack");
+                        continue;
+                    };
+
+                    for (name, value) in &frame.locals{
+                        println!("{name}: {} = {value}", value.type_of());
+                    }
+                },
+
+                "stack" | "bt" => {
+                    for (i, frame) in paused.frames.iter().enumerate(){
+                        println!("#{i} {} at {}:{}", frame.function, display_path(&frame.script), frame.line);
                     }
+                },
+
+                "canvas" => {
+                    if paused.canvases.is_empty(){
+                        println!("No canvas");
+                    }
+                    for (i, canvas) in paused.canvases.iter().enumerate(){
+                        let layer = if canvas.layer.is_some() { ", layer" } else { "" };
+                        println!("{i}: {}x{}{layer}", canvas.width, canvas.height);
+                    }
+                },
+
+                "dump" => {
+                    let Some(canvas) = paused.canvases.first() else {
+                        eprintln!("No canvas to dump");
+                        continue;
+                    };
+
+                    let path = if arg.is_empty() { "dump.png" } else { arg };
+                    match canvas.data.save(path, "png"){
+                        Ok(_) => println!("Wrote the top canvas to {path}"),
+                        Err(e) => println!("Could not save image at {path}:\n\t{e}")
+                    }
+                },
+
+                "help" => {
+                    println!("  continue, c               Runs until a breakpoint");
+                    println!("  next, n                   Runs the statement, stepping over the functions it calls");
+                    println!("  step, s                   Runs until the next statement, entering the functions it calls");
+                    println!("  finish                    Runs until the function returns");
+                    println!("  break, b <file:line>      Adds a breakpoint, the file being the debugged script when only a line is given");
+                    println!("  delete, d <file:line>     Removes a breakpoint");
+                    println!("  breakpoints               Lists the breakpoints");
+                    println!("  locals, l [frame]         Shows the variables of the current function, or of a frame of the stack");
+                    println!("  stack, bt                 Shows the functions being run, the current one first");
+                    println!("  canvas                    Shows the sizes of the canvases of the stack, the top first");
+                    println!("  dump [file.png]           Writes the canvas on top of the stack to a png, dump.png by default");
+                    println!("  quit, q                   Stops the script");
+                },
+
+                "" => {},
+                _ => eprintln!("Unknown command {name}, type help for the commands")
+            }
+        }
+    }
+}
+
+/// Runs the script under the control of a prompt, stopping before its first statement
+fn debug(file:&str, cmd:&Command) -> Result<(), ()>{
+    let Ok(mut program) = compile(Path::new(file)) else {
+        return Err(());
+    };
+
+    if let Some(s) = cmd.value(SEED_OPTION){
+        match s.parse::<i32>(){
+            Ok(seed) => program.set_seed(seed as u32),
+            Err(_) => {
+                eprintln!("Invalid seed: {s}");
+                return Err(());
+            }
+        }
+    }
+
+    let Some((entry, args)) = entry(&program, cmd) else {
+        return Err(());
+    };
+
+    println!("Debugging {entry} of {file}, type help for the commands");
+
+    let mut prompt = Prompt{ main: program.path().to_path_buf(), breakpoints: vec![], sources: HashMap::new() };
+    match program.debug(&entry.name, &args, &mut prompt, true){
+        Ok(saved) => {
+            println!("The script ended, {} canvas(es) saved", saved.len());
+            Ok(())
+        },
+        Err(e) => {
+            println!("The script stopped: {e}");
+            Err(())
+        }
+    }
+}
+
+/// Path relative to the working directory when it is in it
+fn display_path(path:&Path) -> String{
+    let cwd = env::current_dir().unwrap_or_default();
+    path.strip_prefix(&cwd).unwrap_or(path).display().to_string()
+}
+
+/// Reads statements and expressions from the standard input and evaluates them until it ends or `:quit` is typed
+fn repl(cmd: &Command){
+    let mut session = Engine::new().session();
+
+    if let Some(s) = cmd.value(SEED_OPTION){
+        match s.parse::<i32>(){
+            Ok(seed) => session.set_seed(seed as u32),
+            Err(_) => {
+                eprintln!("Invalid seed: {s}");
+                return;
+            }
+        }
+    }
+
+    let preview = cmd.value(PREVIEW_OPTION);
+    if preview.is_some_and(|e| !e.ends_with(".png")){
+        eprintln!("{PREVIEW_OPTION} expects a '.png' file");
+        return;
+    }
+
+    println!("papyrus repl, type :help for the commands");
+
+    let mut input = String::new();
+    loop{
+        print!("{}", if input.is_empty() { "> " } else { "... " });
+        io::stdout().flush().unwrap();
+
+        let mut line = String::new();
+        match io::stdin().read_line(&mut line){
+            Ok(0) => {
+                println!();
+                break;
+            },
+            Ok(_) => input.push_str(&line),
+            Err(e) => {
+                eprintln!("Could not read the input: {e}");
+                break;
+            }
+        }
+
+        // An input goes on while it has unclosed blocks or parentheses
+        let open = input.chars().filter(|e| *e == '{' || *e == '(').count();
+        let closed = input.chars().filter(|e| *e == '}' || *e == ')').count();
+        if open > closed{
+            continue;
+        }
+
+        let text = std::mem::take(&mut input);
+        let text = text.trim();
+
+        if let Some(command) = text.strip_prefix(':'){
+            if !repl_command(&mut session, command.trim()){
+                break;
+            }
+        }else if !text.is_empty(){
+            match session.eval(text){
+                Ok(Value::Void) => {},
+                Ok(value) => println!("{value}"),
+                Err(EvalError::Runtime(e)) => eprintln!("Runtime error: {e}"),
+                Err(e) => eprintln!("{e}")
+            }
+        }
+
+        if let (Some(path), Some(canvas)) = (preview, session.canvases().first()){
+            if let Err(e) = canvas.data.save(path, "png"){
+                println!("Could not save image at {path}:\n\t{e}");
+            }
+        }
+    }
+}
+
+/// Runs a command typed after `:` at the prompt of the repl, returns false to leave it
+fn repl_command(session:&mut Session, command:&str) -> bool{
+    let (name, arg) = command.split_once(' ').map(|(n, a)| (n, a.trim())).unwrap_or((command, ""));
+
+    match name{
+        "quit" | "q" => return false,
+
+        "save" => {
+            let Some(canvas) = session.canvases().first() else {
+                eprintln!("No canvas to save, create one with create_canvas");
+                return true;
+            };
+
+            let format = Path::new(arg).extension().and_then(|e| e.to_str()).unwrap_or_default();
+            if !IMG_FORMAT.contains(&format){
+                eprintln!("Expected a file ending with one of: {}", IMG_FORMAT.join(", "));
+                return true;
+            }
+
+            if let Err(e) = canvas.data.save(arg, format){
+                println!("Could not save image at {arg}:\n\t{e}");
+            }
+        },
+
+        "canvas" => {
+            if session.canvases().is_empty(){
+                println!("No canvas, create one with create_canvas");
+            }
+
+            for (i, canvas) in session.canvases().iter().enumerate(){
+                let depth = format!("{:?}", canvas.data.depth()).to_lowercase();
+                match &canvas.layer{
+                    Some(layer) => println!("{i}: {}x{} {depth}, layer {:?} at {}", canvas.width, canvas.height, layer.blend, layer.opacity),
+                    None => println!("{i}: {}x{} {depth}", canvas.width, canvas.height)
                 }
             }
+            println!("{} canvas(es) saved", session.saved().len());
+        },
+
+        "import" => {
+            let (path, alias) = match arg.split_once(" as "){
+                Some((path, alias)) => (path.trim(), Some(alias.trim())),
+                None => (arg, None)
+            };
+
+            if path.is_empty(){
+                eprintln!("Expected :import <script> [as <name>]");
+                return true;
+            }
+
+            let path = path.strip_suffix(".pprs").unwrap_or(path);
+            let statement = match alias{
+                Some(alias) => format!("import \"{path}\" as {alias};"),
+                None => format!("import \"{path}\";")
+            };
+
+            if let Err(e) = session.eval(&statement){
+                eprintln!("{e}");
+            }
+        },
+
+        "help" => {
+            println!("Type statements or expressions, the value of an expression is printed");
+            println!("  :save <file>                Writes the canvas on top of the stack, as one of: {}", IMG_FORMAT.join(", "));
+            println!("  :canvas                     Lists the canvases of the stack, the top first");
+            println!("  :import <script> [as name]  Imports a script, its path being relative to the working directory");
+            println!("  :quit                       Leaves the repl");
+        },
+
+        _ => eprintln!("Unknown command :{name}, type :help for the commands")
+    }
+
+    true
+}
+
+/// Modification time and size of each file, `None` for a missing one
+fn versions(paths:&[PathBuf]) -> Vec<Option<(SystemTime, u64)>>{
+    paths.iter().map(|e| fs::metadata(e).and_then(|m| Ok((m.modified()?, m.len()))).ok()).collect()
+}
+
+/// The function run by `cmd` along with its arguments, printing why it can't be run.
+/// An overloaded function is its first definition able to read the arguments
+fn entry<'a>(program:&'a Program, cmd:&Command) -> Option<(&'a Function, Vec<Value>)>{
+    let entry_name = cmd.value(ENTRY_OPTION).unwrap_or(DEFAULT_ENTRY);
+    let overloads = program.functions(entry_name).into_iter().filter(|e| e.public || e.name == DEFAULT_ENTRY).collect::<Vec<&Function>>();
+
+    if overloads.is_empty(){
+        if program.function(entry_name).is_some(){
+            eprintln!("The function {entry_name} is not public, only {DEFAULT_ENTRY} and public functions can be run");
+        }else{
+            eprintln!("No function named {entry_name} in {}", program.path().display());
         }
+        return None;
+    }
+
+    let mut errors = vec![];
+    for entry in &overloads{
+        match entry_args(entry, cmd){
+            Ok(args) => return Some((entry, args)),
+            Err(e) => errors.push(e)
+        }
+    }
+
+    if let [error] = errors.as_slice(){
+        eprintln!("{error}");
     }else{
-        eprintln!("Non-UTF8 chars found on the filename");
+        eprintln!("No definition of {entry_name} takes these arguments:");
+        for (entry, error) in overloads.iter().zip(errors){
+            eprintln!("  {entry}: {error}");
+        }
     }
-    
+    None
+}
+
+/// Reads the arguments of `entry` given in order after `--`, or by name with `--arg name=value`
+fn entry_args(entry:&Function, cmd:&Command) -> Result<Vec<Value>, String>{
+    let name = &entry.name;
+    if cmd.script_args.len() > entry.params.len(){
+        return Err(format!("{name} takes {} arguments but {} were given", entry.params.len(), cmd.script_args.len()));
+    }
+
+    let mut texts = cmd.script_args.iter().map(|e| Some(e.as_str())).collect::<Vec<Option<&str>>>();
+    texts.resize(entry.params.len(), None);
+
+    for arg in cmd.values.get(ARG_OPTION).into_iter().flatten(){
+        let Some((param, value)) = arg.split_once('=') else {
+            return Err(format!("Expected <name>=<value> after {ARG_OPTION}, got '{arg}'"));
+        };
+
+        let Some(i) = entry.params.iter().position(|e| e.0 == param) else {
+            return Err(format!("{name} has no parameter named '{param}'"));
+        };
+
+        if texts[i].is_some(){
+            return Err(format!("The argument '{param}' of {name} is given more than once"));
+        }
+        texts[i] = Some(value);
+    }
+
+    entry.params.iter().zip(texts).map(|((param, t), text)| {
+        let text = text.ok_or_else(|| format!("Missing the argument '{param}' of {name}, of type {t}"))?;
+        Value::parse(text, *t).map_err(|e| format!("Invalid argument '{param}' of {name}: {e}"))
+    }).collect()
+}
 
+/// Writes the layers of every saved canvas, a canvas saved without layers being its only layer
+fn export_layers(saved:&[(Canvas, Vec<Canvas>)], format:&str){
+    for (i, (canvas, layers)) in saved.iter().enumerate(){
+        let layers = if layers.is_empty(){ std::slice::from_ref(canvas) } else { layers.as_slice() };
+
+        if format == LAYER_FORMAT[0]{
+            for (j, layer) in layers.iter().enumerate(){
+                if let Err(e) = layer.data.save(&format!("canvas{i}_layer{j}.png"), "png"){
+                    println!("Could not save image at canvas{i}_layer{j}.png:\n\t{e}");
+                }
+            }
+
+        }else if format == LAYER_FORMAT[1]{
+            if let Err(e) = ora::save(&format!("canvas{i}.ora"), canvas, layers){
+                println!("Could not save image at canvas{i}.ora:\n\t{e}");
+            }
+        }
+    }
 }
 
 fn read_cmd<'a>(args: &'a[String]) -> Command<'a>{
     let name = &args[0];
-    let mut cmd = Command{name, args: vec![], options: HashSet::new()};
+    let mut cmd = Command{name, args: vec![], options: HashSet::new(), values: HashMap::new(), script_args: vec![]};
 
-
-    for arg in &args[1..]{
-        if !arg.starts_with("--"){
+    let mut iter = args[1..].iter();
+    while let Some(arg) = iter.next(){
+        if arg == ARGS_SEPARATOR{
+            cmd.script_args = iter.by_ref().cloned().collect();
+        }else if !arg.starts_with("--"){
             cmd.args.push(arg.to_string());
+        }else if VALUED_OPTIONS.contains(&arg.as_str()){
+            match iter.next(){
+                Some(value) => cmd.values.entry(arg.to_string()).or_default().push(value.to_string()),
+                None => eprintln!("Expected a value after {arg}")
+            }
         }else{
             cmd.options.insert(arg.to_string());
         }
@@ -218,6 +773,40 @@ fn help(){
     println!("  Runs a script file");
     println!("  Sets the output video file format");
     println!("  Also generates the individual frames of the video if the option --export-frames is set");
+    println!();
+    println!("papyrus run <script> [options] {ARGS_SEPARATOR} <arguments>");
+    println!("  Runs a script file, giving the arguments to its entry function in order");
+    println!();
+    println!("papyrus watch <script> [{IMG_OUTPUT} <format>] [options] [{ARGS_SEPARATOR} <arguments>]");
+    println!("  Runs a script file like run, then again every time it or one of the scripts it imports changes");
+    println!("  Errors are printed and the script keeps being watched, until the process is stopped");
+    println!();
+    println!("papyrus list-entries <script>");
+    println!("  Lists the functions of a script that can be run with {ENTRY_OPTION}: {DEFAULT_ENTRY} and the public functions");
+    println!();
+    println!("papyrus debug <script> [{SEED_OPTION} <int>] [{ENTRY_OPTION} <function>] [{ARG_OPTION} <name=value>] [{ARGS_SEPARATOR} <arguments>]");
+    println!("  Runs a script step by step, stopping before its first statement. Type help at the prompt for the commands");
+    println!();
+    println!("papyrus repl [{SEED_OPTION} <int>] [{PREVIEW_OPTION} <file.png>]");
+    println!("  Evaluates statements and expressions typed at a prompt, the variables, functions and canvases staying from one to the next");
+    println!("  Writes the canvas on top of the stack to the file after each input if {PREVIEW_OPTION} is set");
+    println!();
+    println!("papyrus dap");
+    println!("  Serves the Debug Adapter Protocol on stdin and stdout, for editors to debug scripts");
+    println!();
+    println!("Options of papyrus run:");
+    println!("  {SEED_OPTION} <int>");
+    println!("    Seeds the random number generator so every run gives the same output");
+    println!("  {PALETTE_OPTION} <colors | palette file>");
+    println!("    Reduces the saved images to an indexed png or gif of that many colors, or of the colors of a .gpl or hex palette file");
+    println!("  {DITHER_OPTION} <{}>", format_array(Dither::NAMES, "|"));
+    println!("    Sets the dithering used when reducing the colors, none by default");
+    println!("  {EXPORT_LAYERS_OPTION} <{}>", format_array(LAYER_FORMAT, "|"));
+    println!("    Also writes the layers of every canvas, as one png per layer or as an OpenRaster file");
+    println!("  {ENTRY_OPTION} <function>");
+    println!("    Runs that public function instead of {DEFAULT_ENTRY}");
+    println!("  {ARG_OPTION} <name=value>");
+    println!("    Gives the argument of the parameter name of the entry function, colors being written #rrggbbaa");
 
 }
 
@@ -230,272 +819,3 @@ fn format_array<>(a:&[impl Display], sep:&str) -> String{
 
     str
 }
-
-fn to_rgba(pixel: u32) -> [u8; 4]{
-    let r = ((pixel >> 24) & 0xff) as u8;
-    let g = ((pixel >> 16) & 0xff) as u8;
-    let b = ((pixel >> 8) & 0xff) as u8;
-    let a = (pixel & 0xff) as u8;
-    [r, g, b, a]
-}
-
-fn from_rgba(r: u8, g: u8, b: u8, a: u8) -> u32{
-    let r = (r as u32 & 0xff) << 24;
-    let g = (g as u32 & 0xff) << 16;
-    let b = (b as u32 & 0xff) << 8;
-    let a = a as u32 & 0xff;
-
-    let color = r | g | b | a;
-    color
-
-}
-
-fn init_lexer(lexer:&mut Lexer<TokenType>){
-    let ident_regex = Regex::new()
-        .then(RegexElement::AnyOf(vec![
-            RegexElement::Set('a', 'z', Quantifier::Exactly(1)),
-            RegexElement::Set('A', 'Z', Quantifier::Exactly(1))
-        ]))
-        .then(RegexElement::Group(vec![
-            RegexElement::AnyOf(vec![
-                RegexElement::Item('_', Quantifier::Exactly(1)),
-                RegexElement::Set('a', 'z', Quantifier::Exactly(1)),
-                RegexElement::Set('A', 'Z', Quantifier::Exactly(1)),
-                RegexElement::Set('0', '9', Quantifier::Exactly(1))
-            ])
-        ], Quantifier::ZeroOrMany));
-
-    let float_regex = Regex::new()
-        .then(RegexElement::Item('-', Quantifier::ZeroOrOne))
-        .then(RegexElement::Set('0', '9', Quantifier::OneOrMany))
-        .then(RegexElement::Item('.', Quantifier::Exactly(1)))
-        .then(RegexElement::Set('0', '9', Quantifier::OneOrMany));
-
-    let int_regex = Regex::new()
-        .then(RegexElement::Item('-', Quantifier::ZeroOrOne))
-        .then(RegexElement::Set('0', '9', Quantifier::OneOrMany));
-
-    let hex_regex = Regex::new()
-        .then(RegexElement::Item('#', Quantifier::Exactly(1)))
-        .then(RegexElement::Group(vec![
-            RegexElement::AnyOf(vec![
-                RegexElement::Set('0', '9', Quantifier::Exactly(1)),
-                RegexElement::Set('a', 'f', Quantifier::Exactly(1)),
-                RegexElement::Set('A', 'F', Quantifier::Exactly(1))
-            ])
-        ], Quantifier::Exactly(8)));
-
-    let if_regex = Regex::new()
-        .then(RegexElement::Item('i', Quantifier::Exactly(1)))
-        .then(RegexElement::Item('f', Quantifier::Exactly(1)));
-
-    let else_regex = Regex::new()
-        .then(RegexElement::Item('e', Quantifier::Exactly(1)))
-        .then(RegexElement::Item('l', Quantifier::Exactly(1)))
-        .then(RegexElement::Item('s', Quantifier::Exactly(1)))
-        .then(RegexElement::Item('e', Quantifier::Exactly(1)));
-
-    let while_regex = Regex::new()
-        .then(RegexElement::Item('w', Quantifier::Exactly(1)))
-        .then(RegexElement::Item('h', Quantifier::Exactly(1)))
-        .then(RegexElement::Item('i', Quantifier::Exactly(1)))
-        .then(RegexElement::Item('l', Quantifier::Exactly(1)))
-        .then(RegexElement::Item('e', Quantifier::Exactly(1)));
-
-    let travel_regex = Regex::new()
-        .then(RegexElement::Item('t', Quantifier::Exactly(1)))
-        .then(RegexElement::Item('r', Quantifier::Exactly(1)))
-        .then(RegexElement::Item('a', Quantifier::Exactly(1)))
-        .then(RegexElement::Item('v', Quantifier::Exactly(1)))
-        .then(RegexElement::Item('e', Quantifier::Exactly(1)))
-        .then(RegexElement::Item('l', Quantifier::Exactly(1)));
-
-    let subcanvas_regex = Regex::new()
-        .then(RegexElement::Item('s', Quantifier::Exactly(1)))
-        .then(RegexElement::Item('u', Quantifier::Exactly(1)))
-        .then(RegexElement::Item('b', Quantifier::Exactly(1)))
-        .then(RegexElement::Item('c', Quantifier::Exactly(1)))
-        .then(RegexElement::Item('a', Quantifier::Exactly(1)))
-        .then(RegexElement::Item('n', Quantifier::Exactly(1)))
-        .then(RegexElement::Item('v', Quantifier::Exactly(1)))
-        .then(RegexElement::Item('a', Quantifier::Exactly(1)))
-        .then(RegexElement::Item('s', Quantifier::Exactly(1)));
-
-    let def_regex = Regex::new()
-        .then(RegexElement::Item('d', Quantifier::Exactly(1)))
-        .then(RegexElement::Item('e', Quantifier::Exactly(1)))
-        .then(RegexElement::Item('f', Quantifier::Exactly(1)));
-
-    let return_regex = Regex::new()
-        .then(RegexElement::Item('r', Quantifier::Exactly(1)))
-        .then(RegexElement::Item('e', Quantifier::Exactly(1)))
-        .then(RegexElement::Item('t', Quantifier::Exactly(1)))
-        .then(RegexElement::Item('u', Quantifier::Exactly(1)))
-        .then(RegexElement::Item('r', Quantifier::Exactly(1)))
-        .then(RegexElement::Item('n', Quantifier::Exactly(1)));
-
-    let bool_regex = Regex::new()
-        .then(RegexElement::AnyOf(vec![
-            RegexElement::Group(vec![
-                RegexElement::Item('t', Quantifier::Exactly(1)),
-                RegexElement::Item('r', Quantifier::Exactly(1)),
-                RegexElement::Item('u', Quantifier::Exactly(1)),
-                RegexElement::Item('e', Quantifier::Exactly(1))
-            ], Quantifier::Exactly(1)),
-
-            RegexElement::Group(vec![
-                RegexElement::Item('f', Quantifier::Exactly(1)),
-                RegexElement::Item('a', Quantifier::Exactly(1)),
-                RegexElement::Item('l', Quantifier::Exactly(1)),
-                RegexElement::Item('s', Quantifier::Exactly(1)),
-                RegexElement::Item('e', Quantifier::Exactly(1))
-            ], Quantifier::Exactly(1))
-        ]));
-
-    let gt_eq_regex = Regex::new()
-        .then(RegexElement::Item('>', Quantifier::Exactly(1)))
-        .then(RegexElement::Item('=', Quantifier::Exactly(1)));
-
-    let lt_eq_regex = Regex::new()
-        .then(RegexElement::Item('<', Quantifier::Exactly(1)))
-        .then(RegexElement::Item('=', Quantifier::Exactly(1)));
-    
-    let not_eq_regex = Regex::new()
-        .then(RegexElement::Item('!', Quantifier::Exactly(1)))
-        .then(RegexElement::Item('=', Quantifier::Exactly(1)));
-
-    let sigle_comment_regex = Regex::new()
-        .then(RegexElement::Item('/', Quantifier::Exactly(2)))
-        .then(RegexElement::NoneOf(vec![
-            RegexElement::Item('\n', Quantifier::Exactly(1))
-        ], Quantifier::ZeroOrMany))
-        .then(RegexElement::Item('\n', Quantifier::ZeroOrMany));
-
-    let pub_regex = Regex::new()
-            .then(RegexElement::Item('p', Quantifier::Exactly(1)))
-            .then(RegexElement::Item('u', Quantifier::Exactly(1)))
-            .then(RegexElement::Item('b', Quantifier::Exactly(1)));
-
-    let import_regex = Regex::new()
-            .then(RegexElement::Item('i', Quantifier::Exactly(1)))
-            .then(RegexElement::Item('m', Quantifier::Exactly(1)))
-            .then(RegexElement::Item('p', Quantifier::Exactly(1)))
-            .then(RegexElement::Item('o', Quantifier::Exactly(1)))
-            .then(RegexElement::Item('r', Quantifier::Exactly(1)))
-            .then(RegexElement::Item('t', Quantifier::Exactly(1)));
-
-    let as_regex = Regex::new()
-            .then(RegexElement::Item('a', Quantifier::Exactly(1)))
-            .then(RegexElement::Item('s', Quantifier::Exactly(1)));
-
-    let string_regex = Regex::new()
-            .then(RegexElement::Item('"', Quantifier::Exactly(1)))
-            .then(RegexElement::NoneOf(vec![
-                RegexElement::Item('"', Quantifier::Exactly(1))
-            ], Quantifier::ZeroOrMany))
-            .then(RegexElement::Item('"', Quantifier::Exactly(1)));
-
-
-    lexer.register(LexerNode::new(if_regex, TokenType::If));
-    lexer.register(LexerNode::new(else_regex, TokenType::Else));
-    lexer.register(LexerNode::new(while_regex, TokenType::While));
-    //lexer.register(LexerNode::new(travel_regex, TokenType::Travel));
-    lexer.register(LexerNode::new(subcanvas_regex, TokenType::Subcanvas));
-    lexer.register(LexerNode::new(def_regex, TokenType::Def));
-    lexer.register(LexerNode::new(return_regex, TokenType::Return));
-
-    lexer.register(LexerNode::new(bool_regex, TokenType::Bool));
-    lexer.register(LexerNode::new(float_regex, TokenType::Float));
-    lexer.register(LexerNode::new(int_regex, TokenType::Int));
-    lexer.register(LexerNode::new(hex_regex, TokenType::Hex));
-
-    lexer.register(LexerNode::new(pub_regex, TokenType::Pub));
-    lexer.register(LexerNode::new(import_regex, TokenType::Import));
-    lexer.register(LexerNode::new(as_regex, TokenType::As));
-    lexer.register(LexerNode::new(string_regex, TokenType::String));
-
-    lexer.register(LexerNode::new(ident_regex, TokenType::Ident));
-
-
-
-    lexer.register(LexerNode::new(Regex::new().then(RegexElement::Item('(', Quantifier::Exactly(1))), TokenType::LParen));
-    lexer.register(LexerNode::new(Regex::new().then(RegexElement::Item(')', Quantifier::Exactly(1))), TokenType::RParen));
-
-    lexer.register(LexerNode::new(Regex::new().then(RegexElement::Item('{', Quantifier::Exactly(1))), TokenType::LBracket));
-    lexer.register(LexerNode::new(Regex::new().then(RegexElement::Item('}', Quantifier::Exactly(1))), TokenType::RBracket));
-
-    lexer.register(LexerNode::new(Regex::new().then(RegexElement::Item(',', Quantifier::Exactly(1))), TokenType::Comma));
-    lexer.register(LexerNode::new(Regex::new().then(RegexElement::Item(':', Quantifier::Exactly(1))), TokenType::Colon));
-    lexer.register(LexerNode::new(Regex::new().then(RegexElement::Item(';', Quantifier::Exactly(1))), TokenType::SemiColon));
-    lexer.register(LexerNode::new(Regex::new().then(RegexElement::Item('.', Quantifier::Exactly(1))), TokenType::Dot));
-
-    lexer.register(LexerNode::new(Regex::new().then(RegexElement::Item('+', Quantifier::Exactly(1))), TokenType::Plus));
-    lexer.register(LexerNode::new(Regex::new().then(RegexElement::Item('-', Quantifier::Exactly(1))), TokenType::Minus));
-    lexer.register(LexerNode::new(Regex::new().then(RegexElement::Item('*', Quantifier::Exactly(1))), TokenType::Mul));
-    
-    lexer.register(LexerNode::new(sigle_comment_regex, TokenType::SingleComment));
-    lexer.register(LexerNode::new(Regex::new().then(RegexElement::Item('/', Quantifier::Exactly(1))), TokenType::Div));
-    lexer.register(LexerNode::new(Regex::new().then(RegexElement::Item('%', Quantifier::Exactly(1))), TokenType::Mod));
-    lexer.register(LexerNode::new(Regex::new().then(RegexElement::Item('^', Quantifier::Exactly(1))), TokenType::Pow));
-
-
-    lexer.register(LexerNode::new(Regex::new().then(RegexElement::Item('&', Quantifier::Exactly(2))), TokenType::And));
-    lexer.register(LexerNode::new(Regex::new().then(RegexElement::Item('|', Quantifier::Exactly(2))), TokenType::Or));
-
-    lexer.register(LexerNode::new(Regex::new().then(RegexElement::Item('=', Quantifier::Exactly(2))), TokenType::DoubleEq));
-    lexer.register(LexerNode::new(Regex::new().then(RegexElement::Item('=', Quantifier::Exactly(1))), TokenType::Eq));
-
-    lexer.register(LexerNode::new(gt_eq_regex, TokenType::GTEq));
-    lexer.register(LexerNode::new(lt_eq_regex, TokenType::LTEq));
-    lexer.register(LexerNode::new(not_eq_regex, TokenType::NotEq));
-
-    lexer.register(LexerNode::new(Regex::new().then(RegexElement::Item('>', Quantifier::Exactly(1))), TokenType::GT));
-    lexer.register(LexerNode::new(Regex::new().then(RegexElement::Item('<', Quantifier::Exactly(1))), TokenType::LT));
-    lexer.register(LexerNode::new(Regex::new().then(RegexElement::Item('!', Quantifier::Exactly(1))), TokenType::Not));
-
-    
-}
-
-
-pub fn tokenize(path: &str) -> LexingResult<TokenType>{
-    let mut lexer = Lexer::new();
-    init_lexer(&mut lexer);
-
-    lexer.tokenize_file(path)
-}
-
-pub fn prepare(path: &str) -> Vec<AST<Token<TokenType>>>{
-    match tokenize(path){
-        LexingResult::Ok(tokens) => {
-            match parser::parse(&tokens, true){
-                Some(forest) => {
-                    if verify(&forest, None, &mut Environment::default()){
-                        forest
-                    }else {vec![]}
-                },
-
-                None => {
-                    eprintln!("Could not parse {path}");
-                    vec![]
-                }
-            }
-        },
-
-        LexingResult::Err(errs) => {
-            for e in errs{
-                eprintln!("{e}");
-            }
-            vec![]
-        }
-    }
-}
-
-fn parse(path: &str) -> Option<Runtime>{
-    let forest = prepare(path);
-    if forest.is_empty(){
-        return None;
-    }
-
-    Some(ir::parse(&forest))
-    
-}
\ No newline at end of file
@@ -0,0 +1,47 @@
+use std::{fmt::Debug, rc::Rc};
+
+use crate::{engine::Value, environment::{FuncSign, Type, builtin_funcs}};
+
+/// Implementation of a native function, called with one value per parameter of its signature
+pub type NativeFn = dyn Fn(&[Value]) -> Result<Value, String>;
+
+/// Functions defined by the host program and callable from the scripts like builtins
+#[derive(Clone, Default)]
+pub struct Natives{
+    funcs: Vec<(FuncSign, Type, Rc<NativeFn>)>
+}
+
+impl Natives{
+    pub fn new() -> Self{
+        Natives::default()
+    }
+
+    /// Registers `func` under `sign`, returning a value of type `ret`.
+    /// Fails if a builtin or another native function already has that signature
+    pub fn register(&mut self, sign:FuncSign, ret:Type, func:impl Fn(&[Value]) -> Result<Value, String> + 'static) -> Result<(), String>{
+        if sign.params.contains(&Type::Void){
+            return Err(format!("The function {sign} can't take a void parameter"));
+        }
+
+        if builtin_funcs().contains_key(&sign) || self.get(&sign).is_some(){
+            return Err(format!("Function '{sign}' already exists"));
+        }
+
+        self.funcs.push((sign, ret, Rc::new(func)));
+        Ok(())
+    }
+
+    pub fn get(&self, sign:&FuncSign) -> Option<(Type, &NativeFn)>{
+        self.funcs.iter().find(|e| &e.0 == sign).map(|e| (e.1, e.2.as_ref()))
+    }
+
+    pub fn signatures(&self) -> impl Iterator<Item = (&FuncSign, Type)>{
+        self.funcs.iter().map(|e| (&e.0, e.1))
+    }
+}
+
+impl Debug for Natives{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.funcs.iter().map(|e| &e.0)).finish()
+    }
+}
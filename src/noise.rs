@@ -0,0 +1,142 @@
+use crate::vm::Rng;
+
+const GRADIENTS:[(f32, f32); 8] = [
+    (1.0, 1.0), (-1.0, 1.0), (1.0, -1.0), (-1.0, -1.0),
+    (1.0, 0.0), (-1.0, 0.0), (0.0, 1.0), (0.0, -1.0)
+];
+
+/// Procedural noise functions sharing a permutation table built from a seed
+#[derive(Debug, Clone)]
+pub struct Noise{
+    perm: Vec<usize>
+}
+
+impl Noise{
+    pub fn new(seed: u32) -> Self{
+        let mut rng = Rng::new(seed);
+        let mut table = (0..256).collect::<Vec<usize>>();
+
+        for i in (1..table.len()).rev(){
+            let j = rng.next_u32() as usize % (i+1);
+            table.swap(i, j);
+        }
+
+        // Doubled so that perm[perm[x] + y] never overflows
+        let mut perm = table.clone();
+        perm.extend(table);
+
+        Noise { perm }
+    }
+
+    fn hash(&self, x: i32, y: i32) -> usize{
+        self.perm[self.perm[(x & 255) as usize] + (y & 255) as usize]
+    }
+
+    fn grad(&self, hash: usize, x: f32, y: f32) -> f32{
+        let (gx, gy) = GRADIENTS[hash & 7];
+        gx * x + gy * y
+    }
+
+    /// Gradient noise in [-1, 1]
+    pub fn perlin(&self, x: f32, y: f32) -> f32{
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let xi = x0 as i32;
+        let yi = y0 as i32;
+        let xf = x - x0;
+        let yf = y - y0;
+
+        let u = fade(xf);
+        let v = fade(yf);
+
+        let aa = self.grad(self.hash(xi, yi), xf, yf);
+        let ba = self.grad(self.hash(xi+1, yi), xf - 1.0, yf);
+        let ab = self.grad(self.hash(xi, yi+1), xf, yf - 1.0);
+        let bb = self.grad(self.hash(xi+1, yi+1), xf - 1.0, yf - 1.0);
+
+        let x1 = lerp(aa, ba, u);
+        let x2 = lerp(ab, bb, u);
+
+        lerp(x1, x2, v).clamp(-1.0, 1.0)
+    }
+
+    /// Simplex noise in [-1, 1]
+    pub fn simplex(&self, x: f32, y: f32) -> f32{
+        let f2 = 0.5 * (3.0f32.sqrt() - 1.0);
+        let g2 = (3.0 - 3.0f32.sqrt()) / 6.0;
+
+        let s = (x + y) * f2;
+        let i = (x + s).floor();
+        let j = (y + s).floor();
+
+        let t = (i + j) * g2;
+        let x0 = x - (i - t);
+        let y0 = y - (j - t);
+
+        let (i1, j1) = if x0 > y0 { (1, 0) } else { (0, 1) };
+
+        let x1 = x0 - i1 as f32 + g2;
+        let y1 = y0 - j1 as f32 + g2;
+        let x2 = x0 - 1.0 + 2.0 * g2;
+        let y2 = y0 - 1.0 + 2.0 * g2;
+
+        let i = i as i32;
+        let j = j as i32;
+
+        let corner = |hash: usize, x: f32, y: f32| {
+            let t = 0.5 - x*x - y*y;
+            if t < 0.0 { 0.0 } else { t*t*t*t * self.grad(hash, x, y) }
+        };
+
+        let n0 = corner(self.hash(i, j), x0, y0);
+        let n1 = corner(self.hash(i + i1, j + j1), x1, y1);
+        let n2 = corner(self.hash(i + 1, j + 1), x2, y2);
+
+        (70.0 * (n0 + n1 + n2)).clamp(-1.0, 1.0)
+    }
+
+    /// Cellular noise: distance to the closest feature point, in [0, 1]
+    pub fn worley(&self, x: f32, y: f32) -> f32{
+        let cx = x.floor() as i32;
+        let cy = y.floor() as i32;
+        let mut closest = f32::MAX;
+
+        for dy in -1..=1{
+            for dx in -1..=1{
+                let h = self.hash(cx + dx, cy + dy);
+                let px = (cx + dx) as f32 + self.perm[h] as f32 / 255.0;
+                let py = (cy + dy) as f32 + self.perm[h + 1] as f32 / 255.0;
+
+                let d = ((px - x).powi(2) + (py - y).powi(2)).sqrt();
+                closest = closest.min(d);
+            }
+        }
+
+        closest.min(1.0)
+    }
+
+    /// Fractal brownian motion: sum of `octaves` layers of perlin noise, in [-1, 1]
+    pub fn fbm(&self, x: f32, y: f32, octaves: i32) -> f32{
+        let mut total = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = 1.0;
+        let mut max = 0.0;
+
+        for _ in 0..octaves.clamp(0, 16){
+            total += self.perlin(x * frequency, y * frequency) * amplitude;
+            max += amplitude;
+            amplitude *= 0.5;
+            frequency *= 2.0;
+        }
+
+        if max == 0.0 { 0.0 } else { total / max }
+    }
+}
+
+fn fade(t: f32) -> f32{
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32{
+    a + (b - a) * t
+}
@@ -0,0 +1,82 @@
+use std::{fs::File, io::{Cursor, Write}};
+
+use image::{ImageFormat, RgbaImage, imageops::{self, FilterType}};
+use zip::{ZipWriter, CompressionMethod, write::FileOptions};
+
+use crate::{vm::Canvas, blend::BlendMode};
+
+/// Largest side of the thumbnail stored in the file
+const THUMBNAIL_SIZE:u32 = 256;
+
+/// Name of the OpenRaster compositing operator matching `mode`, layers never use `replace`
+fn composite_op(mode:BlendMode) -> &'static str{
+    match mode{
+        BlendMode::Normal | BlendMode::Replace => "svg:src-over",
+        BlendMode::Multiply => "svg:multiply",
+        BlendMode::Screen => "svg:screen",
+        BlendMode::Overlay => "svg:overlay",
+        BlendMode::Add => "svg:plus",
+        BlendMode::Darken => "svg:darken",
+        BlendMode::Lighten => "svg:lighten",
+        BlendMode::Difference => "svg:difference"
+    }
+}
+
+fn encode_png(image:&RgbaImage) -> Result<Vec<u8>, String>{
+    let mut bytes = Cursor::new(vec![]);
+    image.write_to(&mut bytes, ImageFormat::Png).map_err(|e| e.to_string())?;
+    Ok(bytes.into_inner())
+}
+
+/// Writes an OpenRaster file of the `layers`, given from the bottom up, flattened as `merged`
+pub fn save(path:&str, merged:&Canvas, layers:&[Canvas]) -> Result<(), String>{
+    let file = File::create(path).map_err(|e| e.to_string())?;
+    let mut zip = ZipWriter::new(file);
+
+    let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    // The mimetype has to be the first entry, uncompressed
+    zip.start_file("mimetype", stored).map_err(|e| e.to_string())?;
+    zip.write_all(b"image/openraster").map_err(|e| e.to_string())?;
+
+    let mut stack = String::new();
+    for (i, layer) in layers.iter().enumerate().rev(){
+        let (opacity, blend) = layer.layer.map(|e| (e.opacity, e.blend)).unwrap_or((1.0, BlendMode::Normal));
+
+        stack.push_str(&format!(
+            "    <layer name=\"layer{i}\" src=\"data/layer{i}.png\" x=\"0\" y=\"0\" opacity=\"{opacity}\" composite-op=\"{}\" visibility=\"visible\"/>\n",
+            composite_op(blend)
+        ));
+
+        zip.start_file(format!("data/layer{i}.png"), stored).map_err(|e| e.to_string())?;
+        zip.write_all(&encode_png(&layer.data.to_rgba8())?).map_err(|e| e.to_string())?;
+    }
+
+    let xml = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<image version=\"0.0.3\" w=\"{}\" h=\"{}\">\n  <stack>\n{stack}  </stack>\n</image>\n",
+        merged.width, merged.height
+    );
+
+    zip.start_file("stack.xml", deflated).map_err(|e| e.to_string())?;
+    zip.write_all(xml.as_bytes()).map_err(|e| e.to_string())?;
+
+    let merged_image = merged.data.to_rgba8();
+
+    zip.start_file("mergedimage.png", stored).map_err(|e| e.to_string())?;
+    zip.write_all(&encode_png(&merged_image)?).map_err(|e| e.to_string())?;
+
+    let scale = (THUMBNAIL_SIZE as f32 / merged.width.max(merged.height).max(1) as f32).min(1.0);
+    let thumbnail = imageops::resize(
+        &merged_image,
+        ((merged.width as f32 * scale).round() as u32).max(1),
+        ((merged.height as f32 * scale).round() as u32).max(1),
+        FilterType::Triangle
+    );
+
+    zip.start_file("Thumbnails/thumbnail.png", stored).map_err(|e| e.to_string())?;
+    zip.write_all(&encode_png(&thumbnail)?).map_err(|e| e.to_string())?;
+
+    zip.finish().map_err(|e| e.to_string())?;
+    Ok(())
+}
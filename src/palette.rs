@@ -0,0 +1,265 @@
+use std::{fs::{self, File}, io::BufWriter, path::Path};
+
+use color_quant::NeuQuant;
+use image::RgbaImage;
+
+/// Largest palette an indexed image can hold
+const MAX_COLORS:usize = 256;
+
+/// 4x4 Bayer matrix, thresholds from 0 to 15
+const BAYER:[[u8; 4]; 4] = [
+    [0, 8, 2, 10],
+    [12, 4, 14, 6],
+    [3, 11, 1, 9],
+    [15, 7, 13, 5]
+];
+
+/// How the quantization error is spread over the image
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Dither{
+    #[default]
+    None,
+    FloydSteinberg,
+    Bayer
+}
+
+impl Dither{
+    pub const NAMES:&'static [&'static str] = &["none", "floyd-steinberg", "bayer"];
+
+    pub fn from_name(name:&str) -> Option<Self>{
+        match name{
+            "none" => Some(Dither::None),
+            "floyd-steinberg" => Some(Dither::FloydSteinberg),
+            "bayer" => Some(Dither::Bayer),
+            _ => None
+        }
+    }
+}
+
+/// Palette asked for on the command line
+#[derive(Debug, Clone)]
+pub enum PaletteSource{
+    /// Picks this many colors for every image
+    Automatic(usize),
+    Fixed(Palette)
+}
+
+impl PaletteSource{
+    /// Reads `value` either as a number of colors or as the path of a palette file
+    pub fn parse(value:&str) -> Result<Self, String>{
+        match value.parse::<usize>(){
+            Ok(size) if (1..=MAX_COLORS).contains(&size) => Ok(PaletteSource::Automatic(size)),
+            Ok(_) => Err(format!("A palette must have between 1 and {} colors", MAX_COLORS)),
+            Err(_) => Palette::load(Path::new(value)).map(PaletteSource::Fixed)
+        }
+    }
+
+    pub fn palette_for(&self, image:&RgbaImage) -> Palette{
+        match self{
+            PaletteSource::Automatic(size) => Palette::automatic(image, *size),
+            PaletteSource::Fixed(palette) => palette.clone()
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Palette{
+    colors: Vec<[u8; 4]>
+}
+
+impl Palette{
+    /// Reads a GIMP palette (`.gpl`) or a list of hex colors, one `RRGGBB` or `RRGGBBAA` per line
+    pub fn load(path:&Path) -> Result<Self, String>{
+        let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let gpl = content.trim_start().starts_with("GIMP Palette");
+
+        let mut colors = vec![];
+        for (i, line) in content.lines().enumerate(){
+            let line = line.trim();
+            if line.is_empty() || (gpl && line.starts_with('#')) || line.starts_with(';') || line.starts_with("//"){
+                continue;
+            }
+
+            let color = if gpl{
+                if line == "GIMP Palette" || line.contains(':'){
+                    continue;
+                }
+                parse_gpl_color(line)
+            }else{
+                parse_hex_color(line)
+            };
+
+            match color{
+                Some(color) => colors.push(color),
+                None => return Err(format!("Invalid color '{}' at line {}", line, i + 1))
+            }
+        }
+
+        if colors.is_empty() || colors.len() > MAX_COLORS{
+            return Err(format!("A palette must have between 1 and {} colors but {} were found", MAX_COLORS, colors.len()));
+        }
+
+        Ok(Palette{ colors })
+    }
+
+    /// Picks `size` opaque colors representing the visible pixels of `image` with the NeuQuant algorithm
+    pub fn automatic(image:&RgbaImage, size:usize) -> Self{
+        let size = size.clamp(1, MAX_COLORS);
+        let pixels = image.pixels().filter(|e| e.0[3] != 0).flat_map(|e| [e.0[0], e.0[1], e.0[2], 255]).collect::<Vec<u8>>();
+        if pixels.is_empty(){
+            return Palette{ colors: vec![[0, 0, 0, 0]] };
+        }
+
+        // Neurons barely trained on a few pixels come back with any color, an image with few enough colors keeps them as they are
+        let mut distinct = pixels.chunks_exact(4).map(|e| [e[0], e[1], e[2], e[3]]).collect::<Vec<[u8; 4]>>();
+        distinct.sort_unstable();
+        distinct.dedup();
+        if distinct.len() <= size{
+            return Palette{ colors: distinct };
+        }
+
+        // The alpha of the neurons is left out for the same reason, every pixel was given as opaque
+        let quant = NeuQuant::new(10, size, &pixels);
+        let colors = quant.color_map_rgba().chunks_exact(4).map(|e| [e[0], e[1], e[2], 255]).collect();
+
+        Palette{ colors }
+    }
+
+    /// Adds a transparent color when `image` has transparent pixels that no color of the palette can represent
+    fn with_transparency(mut self, image:&RgbaImage) -> Self{
+        let transparent = image.pixels().any(|e| e.0[3] == 0);
+        if transparent && self.colors.len() < MAX_COLORS && !self.colors.iter().any(|e| e[3] == 0){
+            self.colors.push([0, 0, 0, 0]);
+        }
+        self
+    }
+
+    fn nearest(&self, color:[f32; 4]) -> usize{
+        if color[3] <= 0.0{
+            if let Some(index) = self.transparent_index(){
+                return index;
+            }
+        }
+
+        let distance = |c:&[u8; 4]| (0..4).map(|i| (c[i] as f32 - color[i]).powi(2)).sum::<f32>();
+
+        self.colors.iter().enumerate()
+            .min_by(|(_, a), (_, b)| distance(a).total_cmp(&distance(b)))
+            .map(|(i, _)| i)
+            .unwrap_or_default()
+    }
+
+    fn transparent_index(&self) -> Option<usize>{
+        self.colors.iter().position(|e| e[3] == 0)
+    }
+}
+
+fn parse_gpl_color(line:&str) -> Option<[u8; 4]>{
+    let mut channels = line.split_whitespace().take(3).map(|e| e.parse::<u8>().ok());
+    Some([channels.next()??, channels.next()??, channels.next()??, 255])
+}
+
+fn parse_hex_color(line:&str) -> Option<[u8; 4]>{
+    let hex = line.split_whitespace().next()?.trim_start_matches('#');
+    let channel = |i:usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+
+    match hex.len(){
+        6 => Some([channel(0)?, channel(2)?, channel(4)?, 255]),
+        8 => Some([channel(0)?, channel(2)?, channel(4)?, channel(6)?]),
+        _ => None
+    }
+}
+
+/// An image made of indices in a palette
+pub struct Indexed{
+    width: u32,
+    height: u32,
+    palette: Palette,
+    indices: Vec<u8>
+}
+
+impl Indexed{
+    pub fn new(image:&RgbaImage, palette:Palette, dither:Dither) -> Self{
+        let palette = palette.with_transparency(image);
+        let (width, height) = image.dimensions();
+
+        let mut pixels = image.pixels().map(|e| e.0.map(|c| c as f32)).collect::<Vec<[f32; 4]>>();
+        let mut indices = Vec::with_capacity(pixels.len());
+
+        // Spread of the Bayer thresholds, about the distance between two colors of the palette
+        let spread = 255.0 / (palette.colors.len() as f32).cbrt();
+
+        for y in 0..height as usize{
+            for x in 0..width as usize{
+                let mut color = pixels[y * width as usize + x];
+
+                if dither == Dither::Bayer{
+                    let threshold = (BAYER[y % 4][x % 4] as f32 + 0.5) / 16.0 - 0.5;
+                    for c in color.iter_mut().take(3){
+                        *c += threshold * spread;
+                    }
+                }
+
+                let index = palette.nearest(color);
+                indices.push(index as u8);
+
+                if dither == Dither::FloydSteinberg{
+                    let chosen = palette.colors[index];
+                    let error = [0, 1, 2].map(|i| color[i] - chosen[i] as f32);
+
+                    for (dx, dy, weight) in [(1, 0, 7.0), (-1, 1, 3.0), (0, 1, 5.0), (1, 1, 1.0)]{
+                        let (nx, ny) = (x as i64 + dx, y as i64 + dy);
+                        if nx < 0 || nx >= width as i64 || ny >= height as i64{
+                            continue;
+                        }
+
+                        let neighbour = &mut pixels[ny as usize * width as usize + nx as usize];
+                        for i in 0..3{
+                            neighbour[i] += error[i] * weight / 16.0;
+                        }
+                    }
+                }
+            }
+        }
+
+        Indexed{ width, height, palette, indices }
+    }
+
+    pub fn save_png(&self, path:&str) -> Result<(), String>{
+        let file = File::create(path).map_err(|e| e.to_string())?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), self.width, self.height);
+
+        encoder.set_color(png::ColorType::Indexed);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_palette(self.palette.colors.iter().flat_map(|e| [e[0], e[1], e[2]]).collect::<Vec<u8>>());
+
+        if self.palette.colors.iter().any(|e| e[3] != 255){
+            encoder.set_trns(self.palette.colors.iter().map(|e| e[3]).collect::<Vec<u8>>());
+        }
+
+        let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+        writer.write_image_data(&self.indices).map_err(|e| e.to_string())
+    }
+
+    /// GIF only has a single fully transparent color, partially transparent colors are written opaque
+    pub fn save_gif(&self, path:&str) -> Result<(), String>{
+        let (width, height) = (u16::try_from(self.width), u16::try_from(self.height));
+        let (Ok(width), Ok(height)) = (width, height) else {
+            return Err(format!("A gif can't be larger than {}x{}", u16::MAX, u16::MAX));
+        };
+
+        let file = File::create(path).map_err(|e| e.to_string())?;
+        let palette = self.palette.colors.iter().flat_map(|e| [e[0], e[1], e[2]]).collect::<Vec<u8>>();
+        let mut encoder = gif::Encoder::new(BufWriter::new(file), width, height, &palette).map_err(|e| e.to_string())?;
+
+        let frame = gif::Frame{
+            width,
+            height,
+            transparent: self.palette.transparent_index().map(|e| e as u8),
+            buffer: self.indices.as_slice().into(),
+            ..gif::Frame::default()
+        };
+
+        encoder.write_frame(&frame).map_err(|e| e.to_string())
+    }
+}
@@ -1,6 +1,6 @@
-use neoglot_lib::{lexer::Token, parser::{Parser, AST, expression::{ExpressionParser, Expr, Operator, Position}, expect}, regex::{Regex, RegexElement, Quantifier}, report};
+use neoglot_lib::{lexer::Token, parser::{Parser, AST, expression::{ExpressionParser, Expr, Operator, Position}, expect}, regex::{Regex, RegexElement, Quantifier}};
 
-use crate::TokenType;
+use crate::{TokenType, engine::report};
 
 fn typed_var_assign_regex() -> Regex<TokenType>{
     Regex::new()
@@ -687,7 +687,7 @@ fn parse_subcanvas(parser:&mut Parser<TokenType>, semicolon_terminated:bool) ->
                 let args = args.unwrap();
                 if args.len() != 4 {
                     success = false;
-                    report(&format!("subcanvas takes 4 arguments but {} were provided", args.len()), subcanvas_tok.location.clone());
+                    report(&format!("{} takes 4 arguments but {} were provided", subcanvas_tok.literal, args.len()), subcanvas_tok.location.clone());
                 }
 
                 for param in args{
@@ -990,6 +990,17 @@ fn parse_expression(tokens: &[Token<TokenType>]) -> Option<AST<Expr<TokenType>>>
     })
 }
 
+/// Parses `tokens` as a single expression, outside of any statement
+pub fn parse_expr(tokens:&[Token<TokenType>]) -> Option<AST<Token<TokenType>>>{
+    match parse_expression(tokens){
+        Some(expr) => normalize_expression(expr),
+        None => {
+            report("Could not parse expression", tokens[0].location.clone());
+            None
+        }
+    }
+}
+
 fn illegal_in_expression(kind:TokenType) -> bool{
     kind == TokenType::Def
     || kind == TokenType::While
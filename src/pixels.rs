@@ -0,0 +1,148 @@
+use std::{fs::File, io::BufWriter};
+
+use image::{DynamicImage, ImageBuffer, ImageFormat, Primitive, Rgb, Rgba, RgbaImage, Rgba32FImage, codecs::hdr::HdrEncoder};
+
+pub type Rgba16Image = ImageBuffer<Rgba<u16>, Vec<u16>>;
+
+/// Storage of the channels of a canvas
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Depth{
+    #[default]
+    Rgba8,
+    Rgba16,
+    Rgba32F
+}
+
+impl Depth{
+    pub const NAMES:&'static [&'static str] = &["rgba8", "rgba16", "rgba32f"];
+
+    pub fn from_name(name:&str) -> Option<Self>{
+        match name{
+            "rgba8" => Some(Depth::Rgba8),
+            "rgba16" => Some(Depth::Rgba16),
+            "rgba32f" => Some(Depth::Rgba32F),
+            _ => None
+        }
+    }
+}
+
+/// Subpixel of a canvas, seen as a float where 0 is black and 1 is white.
+/// Integer channels are clamped to that range, float channels keep any value
+pub trait Channel: Primitive + 'static{
+    fn to_unit(self) -> f32;
+    fn from_unit(value:f32) -> Self;
+}
+
+impl Channel for u8{
+    fn to_unit(self) -> f32{ self as f32 / 255.0 }
+    fn from_unit(value:f32) -> Self{ (value.clamp(0.0, 1.0) * 255.0).round() as u8 }
+}
+
+impl Channel for u16{
+    fn to_unit(self) -> f32{ self as f32 / 65535.0 }
+    fn from_unit(value:f32) -> Self{ (value.clamp(0.0, 1.0) * 65535.0).round() as u16 }
+}
+
+impl Channel for f32{
+    fn to_unit(self) -> f32{ self }
+    fn from_unit(value:f32) -> Self{ value }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Pixels{
+    Rgba8(RgbaImage),
+    Rgba16(Rgba16Image),
+    Rgba32F(Rgba32FImage)
+}
+
+/// Evaluates `$body` with `$image` bound to the buffer, whatever its depth
+macro_rules! each_depth{
+    ($pixels:expr, $image:ident => $body:expr) => {
+        match $pixels{
+            $crate::pixels::Pixels::Rgba8($image) => $body,
+            $crate::pixels::Pixels::Rgba16($image) => $body,
+            $crate::pixels::Pixels::Rgba32F($image) => $body
+        }
+    };
+}
+
+/// Same as `each_depth`, `$body` gives a new buffer of the same depth
+macro_rules! map_depth{
+    ($pixels:expr, $image:ident => $body:expr) => {
+        match $pixels{
+            $crate::pixels::Pixels::Rgba8($image) => $crate::pixels::Pixels::Rgba8($body),
+            $crate::pixels::Pixels::Rgba16($image) => $crate::pixels::Pixels::Rgba16($body),
+            $crate::pixels::Pixels::Rgba32F($image) => $crate::pixels::Pixels::Rgba32F($body)
+        }
+    };
+}
+
+pub(crate) use {each_depth, map_depth};
+
+impl Pixels{
+    pub fn new(width:u32, height:u32, depth:Depth) -> Self{
+        match depth{
+            Depth::Rgba8 => Pixels::Rgba8(ImageBuffer::new(width, height)),
+            Depth::Rgba16 => Pixels::Rgba16(ImageBuffer::new(width, height)),
+            Depth::Rgba32F => Pixels::Rgba32F(ImageBuffer::new(width, height))
+        }
+    }
+
+    pub fn depth(&self) -> Depth{
+        match self{
+            Pixels::Rgba8(_) => Depth::Rgba8,
+            Pixels::Rgba16(_) => Depth::Rgba16,
+            Pixels::Rgba32F(_) => Depth::Rgba32F
+        }
+    }
+
+    pub fn dimensions(&self) -> (u32, u32){
+        each_depth!(self, image => image.dimensions())
+    }
+
+    pub fn get(&self, x:u32, y:u32) -> [f32; 4]{
+        each_depth!(self, image => image.get_pixel(x, y).0.map(Channel::to_unit))
+    }
+
+    pub fn put(&mut self, x:u32, y:u32, color:[f32; 4]){
+        each_depth!(self, image => image.put_pixel(x, y, Rgba(color.map(Channel::from_unit))))
+    }
+
+    fn to_dynamic(&self) -> DynamicImage{
+        match self{
+            Pixels::Rgba8(image) => DynamicImage::ImageRgba8(image.clone()),
+            Pixels::Rgba16(image) => DynamicImage::ImageRgba16(image.clone()),
+            Pixels::Rgba32F(image) => DynamicImage::ImageRgba32F(image.clone())
+        }
+    }
+
+    pub fn to_rgba8(&self) -> RgbaImage{
+        match self{
+            Pixels::Rgba8(image) => image.clone(),
+            _ => self.to_dynamic().to_rgba8()
+        }
+    }
+
+    /// Writes the pixels at `path` in `format`: "png", "jpg", "exr" or "hdr".
+    /// Deeper canvases are written as 16-bit pngs, and the hdr format has no alpha
+    pub fn save(&self, path:&str, format:&str) -> Result<(), String>{
+        let result = match format{
+            "png" => match self{
+                Pixels::Rgba8(image) => image.save_with_format(path, ImageFormat::Png),
+                _ => self.to_dynamic().to_rgba16().save_with_format(path, ImageFormat::Png)
+            },
+            "jpg" => self.to_rgba8().save_with_format(path, ImageFormat::Jpeg),
+            "exr" => self.to_dynamic().to_rgba32f().save_with_format(path, ImageFormat::OpenExr),
+            "hdr" => {
+                let image = self.to_dynamic().to_rgb32f();
+                let file = File::create(path).map_err(|e| e.to_string())?;
+                let data = image.pixels().copied().collect::<Vec<Rgb<f32>>>();
+
+                HdrEncoder::new(BufWriter::new(file)).encode(&data, image.width() as usize, image.height() as usize)
+            },
+            _ => return Err(format!("Unknown image file format: {format}"))
+        };
+
+        result.map_err(|e| e.to_string())
+    }
+}
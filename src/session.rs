@@ -0,0 +1,210 @@
+use std::{fmt::Display, path::PathBuf, slice};
+
+use neoglot_lib::lexer::{LexingResult, Token};
+
+use crate::{TokenType, tokenize, parser, validator::{verify, verify_expr_type}, environment::Environment, ir::{Instruction, Lowering, Runtime, Script}, vm::{VM, Canvas}, engine::{Host, Value, Diagnostic, Diagnostics, RuntimeError, report_message, take_reported}};
+
+/// Name of the code typed in a session, the scripts it imports are found from the working directory
+const SESSION_FILE:&str = "<repl>";
+
+/// Tokens only found in statements, an input without any of them is an expression
+const STATEMENT_TOKENS:&[TokenType] = &[
+    TokenType::Import, TokenType::Pub, TokenType::Def, TokenType::Return,
+    TokenType::If, TokenType::Else, TokenType::While, TokenType::Travel, TokenType::Subcanvas,
+    TokenType::Eq, TokenType::Colon, TokenType::SemiColon, TokenType::As,
+    TokenType::LBracket, TokenType::RBracket
+];
+
+/// Why an input of a session was rejected
+#[derive(Debug, Clone)]
+pub enum EvalError{
+    /// The input does not compile, nothing of it was run
+    Compile(Diagnostics),
+    /// The input stopped with an error. What it drew is kept, what it declared is not
+    Runtime(RuntimeError)
+}
+
+impl Display for EvalError{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self{
+            EvalError::Compile(diagnostics) => write!(f, "{diagnostics}"),
+            EvalError::Runtime(e) => write!(f, "{e}")
+        }
+    }
+}
+
+impl std::error::Error for EvalError{}
+
+/// Evaluates code one input at a time, as typed at the prompt of `papyrus repl`.
+/// Variables, functions, imports and canvases stay from one input to the next
+#[derive(Debug)]
+pub struct Session{
+    env: Environment,
+    lowering: Lowering,
+    vm: VM<'static>,
+    path: PathBuf,
+    /// Number of inputs run, each one having its own label
+    inputs: usize
+}
+
+impl Session{
+    pub(crate) fn new(host:Host) -> Self{
+        let path = PathBuf::from(SESSION_FILE);
+        let script = Script{ path: path.clone(), program: vec![], functions: vec![] };
+        let runtime = Runtime{ scripts: vec![script], strings: vec![], natives: host.natives.clone(), sources: host.sources.clone() };
+
+        let mut env = Environment::with_host(host.clone());
+        env.scope_level = 1;
+
+        Session{ env, lowering: Lowering::new(&host), vm: VM::new(runtime), path, inputs: 0 }
+    }
+
+    /// Seeds the random number generator for the inputs evaluated afterwards
+    pub fn set_seed(&mut self, seed:u32){
+        self.vm.set_seed(seed);
+    }
+
+    /// Canvases being drawn on, the top of the stack first
+    pub fn canvases(&self) -> &[Canvas]{
+        self.vm.canvases()
+    }
+
+    /// Canvases saved with `save_canvas` so far
+    pub fn saved(&self) -> &[Canvas]{
+        self.vm.get_saved_canvas()
+    }
+
+    /// Evaluates statements, or a single expression whose value is returned.
+    /// Statements and expressions without a value give `Value::Void`, the last `;` can be left out
+    pub fn eval(&mut self, input:&str) -> Result<Value, EvalError>{
+        take_reported();
+
+        let tokens = lex(input)?;
+        let terminated = tokens.last().is_some_and(|e| e.kind == TokenType::SemiColon || e.kind == TokenType::RBracket);
+
+        let expr = match tokens.split_last(){
+            Some((last, rest)) if last.kind == TokenType::SemiColon => rest,
+            _ => &tokens[..]
+        };
+        if expr.is_empty(){
+            return Ok(Value::Void);
+        }
+
+        if expr.iter().all(|e| !STATEMENT_TOKENS.contains(&e.kind)){
+            return self.eval_expr(expr);
+        }
+
+        if terminated{
+            self.eval_statements(&tokens)
+        }else{
+            // The missing `;` goes on a line of its own, to end a trailing comment
+            self.eval_statements(&lex(&format!("{input}\n;"))?)
+        }
+    }
+
+    fn eval_expr(&mut self, tokens:&[Token<TokenType>]) -> Result<Value, EvalError>{
+        let Some(expr) = parser::parse_expr(tokens) else {
+            return Err(compile_error());
+        };
+
+        if verify_expr_type(&expr, &self.env).is_none(){
+            return Err(compile_error());
+        }
+
+        let before = self.lowering.clone();
+        let (code, t) = self.lowering.lower_expr(&expr, &mut self.vm.runtime_mut().strings);
+
+        if let Err(e) = self.run(code, vec![], vec![]){
+            self.lowering.rollback(before);
+            return Err(e);
+        }
+
+        Ok(self.vm.register("_rt", t))
+    }
+
+    fn eval_statements(&mut self, tokens:&[Token<TokenType>]) -> Result<Value, EvalError>{
+        let Some(forest) = parser::parse(tokens, true) else {
+            return Err(compile_error());
+        };
+
+        let mut env = self.env.clone();
+        let mut valid = true;
+
+        for tree in &forest{
+            let declaration = [TokenType::Def, TokenType::Pub, TokenType::Import].contains(&tree.kind.kind);
+
+            // Functions don't see the variables of the session, and are only declared at the top level
+            if declaration{
+                let vars = env.take_vars();
+                env.scope_level = 0;
+
+                if !verify(slice::from_ref(tree), None, &mut env){ valid = false; }
+
+                env.scope_level = 1;
+                env.set_vars(vars);
+
+            }else if !verify(slice::from_ref(tree), None, &mut env){
+                valid = false;
+            }
+        }
+
+        if !valid{
+            return Err(compile_error());
+        }
+
+        let before = (self.lowering.clone(), std::mem::replace(&mut self.env, env));
+        let (code, defs, imports) = self.lowering.lower(&forest, &mut self.vm.runtime_mut().strings);
+
+        if let Err(e) = self.run(code, defs, imports){
+            self.lowering.rollback(before.0);
+            self.env = before.1;
+            return Err(e);
+        }
+
+        Ok(Value::Void)
+    }
+
+    /// Adds the code of an input to the program and runs its statements
+    fn run(&mut self, code:Vec<Instruction>, defs:Vec<Instruction>, imports:Vec<Script>) -> Result<(), EvalError>{
+        self.inputs += 1;
+        let label = format!("<{}>", self.inputs);
+
+        let runtime = self.vm.runtime_mut();
+        for script in imports{
+            if !runtime.scripts.iter().any(|e| e.path == script.path){
+                runtime.scripts.push(script);
+            }
+        }
+
+        let program = &mut runtime.scripts[0].program;
+        program.extend(defs);
+        program.push(Instruction::Label(label.clone()));
+        program.extend(code);
+        program.push(Instruction::Ret);
+
+        self.vm.resume(&self.path, &label).map_err(|message| EvalError::Runtime(RuntimeError{ message }))
+    }
+}
+
+/// Tokens of an input, without its comments
+fn lex(input:&str) -> Result<Vec<Token<TokenType>>, EvalError>{
+    match tokenize(input, SESSION_FILE){
+        LexingResult::Ok(tokens) => Ok(tokens.into_iter().filter(|e| e.kind != TokenType::SingleComment).collect()),
+        LexingResult::Err(errs) => {
+            for e in errs{
+                report_message(&e.to_string());
+            }
+            Err(compile_error())
+        }
+    }
+}
+
+/// The errors reported while compiling an input
+fn compile_error() -> EvalError{
+    let mut errors = take_reported();
+    if errors.is_empty(){
+        errors.push(Diagnostic{ location: None, message: "Could not parse the input".to_string() });
+    }
+
+    EvalError::Compile(Diagnostics{ errors, scripts: vec![] })
+}
@@ -0,0 +1,96 @@
+use std::{collections::HashMap, fmt::Debug, fs, path::{Component, Path, PathBuf}};
+
+/// Supplies the content of the main script, of the scripts it imports and of the files it loads, such as fonts
+pub trait SourceProvider: Debug{
+    /// Whether there is a script at `path`
+    fn exists(&self, path:&Path) -> bool;
+
+    /// Content of the script at `path`
+    fn read(&self, path:&Path) -> Result<String, String>;
+
+    /// Raw content of the file at `path`, the one of `read` unless the provider holds binary files
+    fn read_bytes(&self, path:&Path) -> Result<Vec<u8>, String>{
+        self.read(path).map(String::into_bytes)
+    }
+}
+
+/// Reads the scripts from the disk
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FileSystem;
+
+impl SourceProvider for FileSystem{
+    fn exists(&self, path:&Path) -> bool{
+        path.exists()
+    }
+
+    fn read(&self, path:&Path) -> Result<String, String>{
+        if !path.is_file(){
+            return Err(format!("The path {} is not a file", path.display()));
+        }
+
+        fs::read_to_string(path).map_err(|e| e.to_string())
+    }
+
+    fn read_bytes(&self, path:&Path) -> Result<Vec<u8>, String>{
+        if !path.is_file(){
+            return Err(format!("The path {} is not a file", path.display()));
+        }
+
+        fs::read(path).map_err(|e| e.to_string())
+    }
+}
+
+/// Scripts held in memory under virtual file names, such as `main.pprs` or `lib/shapes.pprs`.
+/// Imports are resolved against these names the same way they are against the disk.
+/// Binary files, like the fonts loaded by the scripts, can be held too
+#[derive(Debug, Default, Clone)]
+pub struct MemorySources{
+    files: HashMap<PathBuf, Vec<u8>>
+}
+
+impl MemorySources{
+    pub fn new() -> Self{
+        MemorySources::default()
+    }
+
+    /// Adds the script or file `content` under the name `path`, replacing the previous one
+    pub fn insert(&mut self, path:impl AsRef<Path>, content:impl Into<Vec<u8>>){
+        self.files.insert(normalize(path.as_ref()), content.into());
+    }
+
+    pub fn with(mut self, path:impl AsRef<Path>, content:impl Into<Vec<u8>>) -> Self{
+        self.insert(path, content);
+        self
+    }
+}
+
+impl SourceProvider for MemorySources{
+    fn exists(&self, path:&Path) -> bool{
+        self.files.contains_key(&normalize(path))
+    }
+
+    fn read(&self, path:&Path) -> Result<String, String>{
+        String::from_utf8(self.read_bytes(path)?).map_err(|_| format!("The file {} is not valid UTF-8", path.display()))
+    }
+
+    fn read_bytes(&self, path:&Path) -> Result<Vec<u8>, String>{
+        self.files.get(&normalize(path)).cloned().ok_or_else(|| format!("The path {} doesn't exist", path.display()))
+    }
+}
+
+/// Resolves the `.` and `..` of a path so `./lib.pprs`, `a/../lib.pprs` and `lib.pprs` name the same script.
+/// A `..` leading out of a relative path is kept, as there is nothing to resolve it against
+fn normalize(path:&Path) -> PathBuf{
+    let mut components = vec![];
+
+    for component in path.components(){
+        match (component, components.last()){
+            (Component::CurDir, _) => {},
+            (Component::ParentDir, Some(Component::Normal(_))) => { components.pop(); },
+            (Component::ParentDir, Some(Component::RootDir | Component::Prefix(_))) => {},
+            (component, _) => components.push(component)
+        }
+    }
+
+    components.into_iter().collect()
+}
@@ -0,0 +1,223 @@
+use ab_glyph::{Font as _, FontArc, PxScale, ScaleFont, point};
+
+use crate::{vm::Canvas, draw::Pen};
+
+const GLYPH_WIDTH:i32 = 5;
+const GLYPH_HEIGHT:i32 = 8;
+
+/// Font used to render text, the embedded bitmap font unless a TrueType font was loaded
+#[derive(Debug, Clone, Default)]
+pub enum Font{
+    #[default]
+    Bitmap,
+    Truetype(FontArc)
+}
+
+impl Font{
+    /// TrueType font from the content of a `.ttf` or `.otf` file
+    pub fn parse(data:Vec<u8>) -> Result<Self, String>{
+        let font = FontArc::try_from_vec(data).map_err(|e| e.to_string())?;
+        Ok(Font::Truetype(font))
+    }
+
+    /// Width in pixels of the longest line of `text`
+    pub fn text_width(&self, text:&str, size:i32) -> i32{
+        match self{
+            Font::Bitmap => {
+                let scale = bitmap_scale(size);
+                text.lines().map(|line| {
+                    let n = line.chars().count() as i32;
+                    if n == 0 { 0 } else { n * (GLYPH_WIDTH + 1) * scale - scale }
+                }).max().unwrap_or(0)
+            },
+
+            Font::Truetype(font) => {
+                let font = font.as_scaled(PxScale::from(size.max(1) as f32));
+                text.lines().map(|line| {
+                    let mut caret = 0.0;
+                    let mut previous = None;
+                    for c in line.chars(){
+                        let id = font.glyph_id(c);
+                        if let Some(previous) = previous{
+                            caret += font.kern(previous, id);
+                        }
+                        caret += font.h_advance(id);
+                        previous = Some(id);
+                    }
+                    caret.ceil() as i32
+                }).max().unwrap_or(0)
+            }
+        }
+    }
+}
+
+/// The bitmap font is only scaled by whole factors so that it stays crisp
+fn bitmap_scale(size:i32) -> i32{
+    (size / GLYPH_HEIGHT).max(1)
+}
+
+/// Text rasterization, (`x`, `y`) is the top left corner of the first line
+impl Canvas{
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_text(&mut self, x:i32, y:i32, text:&str, pixel:u32, size:i32, font:&Font, pen:&Pen){
+        match font{
+            Font::Bitmap => self.draw_bitmap_text(x, y, text, pixel, size, pen),
+            Font::Truetype(font) => self.draw_truetype_text(x, y, text, pixel, size, font, pen)
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn draw_bitmap_text(&mut self, x:i32, y:i32, text:&str, pixel:u32, size:i32, pen:&Pen){
+        let scale = bitmap_scale(size);
+
+        for (line_index, line) in text.lines().enumerate(){
+            let top = y + line_index as i32 * (GLYPH_HEIGHT + 1) * scale;
+
+            for (i, c) in line.chars().enumerate(){
+                let left = x + i as i32 * (GLYPH_WIDTH + 1) * scale;
+                let glyph = if (' '..='~').contains(&c) { c } else { '?' };
+                let rows = BITMAP_FONT[glyph as usize - ' ' as usize];
+
+                for (row, bits) in rows.iter().enumerate(){
+                    for column in 0..GLYPH_WIDTH{
+                        if bits & (1 << (GLYPH_WIDTH - 1 - column)) != 0{
+                            self.fill_rect(left + column * scale, top + row as i32 * scale, scale, scale, pixel, pen);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn draw_truetype_text(&mut self, x:i32, y:i32, text:&str, pixel:u32, size:i32, font:&FontArc, pen:&Pen){
+        let scale = PxScale::from(size.max(1) as f32);
+        let scaled = font.as_scaled(scale);
+        let line_height = scaled.height() + scaled.line_gap();
+
+        for (line_index, line) in text.lines().enumerate(){
+            let baseline = y as f32 + scaled.ascent() + line_index as f32 * line_height;
+            let mut caret = x as f32;
+            let mut previous = None;
+
+            for c in line.chars(){
+                let id = scaled.glyph_id(c);
+                if let Some(previous) = previous{
+                    caret += scaled.kern(previous, id);
+                }
+
+                let glyph = id.with_scale_and_position(scale, point(caret, baseline));
+                caret += scaled.h_advance(id);
+                previous = Some(id);
+
+                let Some(outline) = font.outline_glyph(glyph) else { continue };
+                let bounds = outline.px_bounds();
+                let (left, top) = (bounds.min.x as i32, bounds.min.y as i32);
+
+                outline.draw(|gx, gy, coverage| {
+                    let coverage = if pen.antialias { coverage } else if coverage >= 0.5 { 1.0 } else { 0.0 };
+                    self.plot(left + gx as i32, top + gy as i32, pixel, coverage, pen.blend);
+                });
+            }
+        }
+    }
+}
+
+/// 5x8 glyphs of the printable ASCII characters, one byte per row, the last row holds the descenders
+const BITMAP_FONT:[[u8; 8]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // space
+    [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04, 0x00], // !
+    [0x0A, 0x0A, 0x0A, 0x00, 0x00, 0x00, 0x00, 0x00], // "
+    [0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A, 0x00], // #
+    [0x04, 0x0F, 0x14, 0x0E, 0x05, 0x1E, 0x04, 0x00], // $
+    [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03, 0x00], // %
+    [0x0C, 0x12, 0x14, 0x08, 0x15, 0x12, 0x0D, 0x00], // &
+    [0x04, 0x04, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00], // '
+    [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02, 0x00], // (
+    [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08, 0x00], // )
+    [0x00, 0x04, 0x15, 0x0E, 0x15, 0x04, 0x00, 0x00], // *
+    [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00, 0x00], // +
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08], // ,
+    [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00, 0x00], // -
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C, 0x00], // .
+    [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00, 0x00], // /
+    [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E, 0x00], // 0
+    [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E, 0x00], // 1
+    [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F, 0x00], // 2
+    [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E, 0x00], // 3
+    [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02, 0x00], // 4
+    [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E, 0x00], // 5
+    [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E, 0x00], // 6
+    [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08, 0x00], // 7
+    [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E, 0x00], // 8
+    [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C, 0x00], // 9
+    [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00, 0x00], // :
+    [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x04, 0x08, 0x00], // ;
+    [0x02, 0x04, 0x08, 0x10, 0x08, 0x04, 0x02, 0x00], // <
+    [0x00, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x00, 0x00], // =
+    [0x08, 0x04, 0x02, 0x01, 0x02, 0x04, 0x08, 0x00], // >
+    [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04, 0x00], // ?
+    [0x0E, 0x11, 0x01, 0x0D, 0x15, 0x15, 0x0E, 0x00], // @
+    [0x0E, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11, 0x00], // A
+    [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E, 0x00], // B
+    [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E, 0x00], // C
+    [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C, 0x00], // D
+    [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F, 0x00], // E
+    [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10, 0x00], // F
+    [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F, 0x00], // G
+    [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11, 0x00], // H
+    [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E, 0x00], // I
+    [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C, 0x00], // J
+    [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11, 0x00], // K
+    [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F, 0x00], // L
+    [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11, 0x00], // M
+    [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11, 0x00], // N
+    [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E, 0x00], // O
+    [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10, 0x00], // P
+    [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D, 0x00], // Q
+    [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11, 0x00], // R
+    [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E, 0x00], // S
+    [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x00], // T
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E, 0x00], // U
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04, 0x00], // V
+    [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A, 0x00], // W
+    [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11, 0x00], // X
+    [0x11, 0x11, 0x0A, 0x04, 0x04, 0x04, 0x04, 0x00], // Y
+    [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F, 0x00], // Z
+    [0x0E, 0x08, 0x08, 0x08, 0x08, 0x08, 0x0E, 0x00], // [
+    [0x00, 0x10, 0x08, 0x04, 0x02, 0x01, 0x00, 0x00], // \
+    [0x0E, 0x02, 0x02, 0x02, 0x02, 0x02, 0x0E, 0x00], // ]
+    [0x04, 0x0A, 0x11, 0x00, 0x00, 0x00, 0x00, 0x00], // ^
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F, 0x00], // _
+    [0x08, 0x04, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00], // `
+    [0x00, 0x00, 0x0E, 0x01, 0x0F, 0x11, 0x0F, 0x00], // a
+    [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x1E, 0x00], // b
+    [0x00, 0x00, 0x0E, 0x10, 0x10, 0x11, 0x0E, 0x00], // c
+    [0x01, 0x01, 0x0D, 0x13, 0x11, 0x11, 0x0F, 0x00], // d
+    [0x00, 0x00, 0x0E, 0x11, 0x1F, 0x10, 0x0E, 0x00], // e
+    [0x06, 0x09, 0x08, 0x1C, 0x08, 0x08, 0x08, 0x00], // f
+    [0x00, 0x00, 0x0F, 0x11, 0x11, 0x0F, 0x01, 0x0E], // g
+    [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x11, 0x00], // h
+    [0x04, 0x00, 0x0C, 0x04, 0x04, 0x04, 0x0E, 0x00], // i
+    [0x02, 0x00, 0x06, 0x02, 0x02, 0x02, 0x12, 0x0C], // j
+    [0x10, 0x10, 0x12, 0x14, 0x18, 0x14, 0x12, 0x00], // k
+    [0x0C, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E, 0x00], // l
+    [0x00, 0x00, 0x1A, 0x15, 0x15, 0x11, 0x11, 0x00], // m
+    [0x00, 0x00, 0x16, 0x19, 0x11, 0x11, 0x11, 0x00], // n
+    [0x00, 0x00, 0x0E, 0x11, 0x11, 0x11, 0x0E, 0x00], // o
+    [0x00, 0x00, 0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10], // p
+    [0x00, 0x00, 0x0F, 0x11, 0x11, 0x0F, 0x01, 0x01], // q
+    [0x00, 0x00, 0x16, 0x19, 0x10, 0x10, 0x10, 0x00], // r
+    [0x00, 0x00, 0x0E, 0x10, 0x0E, 0x01, 0x1E, 0x00], // s
+    [0x08, 0x08, 0x1C, 0x08, 0x08, 0x09, 0x06, 0x00], // t
+    [0x00, 0x00, 0x11, 0x11, 0x11, 0x13, 0x0D, 0x00], // u
+    [0x00, 0x00, 0x11, 0x11, 0x11, 0x0A, 0x04, 0x00], // v
+    [0x00, 0x00, 0x11, 0x11, 0x15, 0x15, 0x0A, 0x00], // w
+    [0x00, 0x00, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x00], // x
+    [0x00, 0x00, 0x11, 0x11, 0x11, 0x0F, 0x01, 0x0E], // y
+    [0x00, 0x00, 0x1F, 0x02, 0x04, 0x08, 0x1F, 0x00], // z
+    [0x02, 0x04, 0x04, 0x08, 0x04, 0x04, 0x02, 0x00], // {
+    [0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x00], // |
+    [0x08, 0x04, 0x04, 0x02, 0x04, 0x04, 0x08, 0x00], // }
+    [0x00, 0x00, 0x08, 0x15, 0x02, 0x00, 0x00, 0x00], // ~
+];
@@ -1,8 +1,8 @@
 use std::path::Path;
 
-use neoglot_lib::{parser, lexer::{Token, LexingResult, Location}, report};
+use neoglot_lib::{parser, lexer::{Token, LexingResult, Location}};
 
-use crate::{TokenType, environment::{Environment, Type, FuncSign}, tokenize};
+use crate::{TokenType, environment::{Environment, Type, FuncSign}, engine::{report, report_message}, tokenize};
 
 type AST = parser::AST<Token<TokenType>>;
 
@@ -174,7 +174,8 @@ fn verify_func_call(func_call_tree: &AST, foreign:bool, env:&Environment) -> boo
 
     }else if env.has_ctx("in_travel") || env.has_ctx("in_subcanvas"){
         if func_sign == (FuncSign{ name: "create_canvas".to_string(), params: vec![Type::Int, Type::Int]})
-        || func_sign == (FuncSign{ name: "save_canvas".to_string(), params: vec![]}){
+        || func_sign == (FuncSign{ name: "save_canvas".to_string(), params: vec![]})
+        || func_sign == (FuncSign{ name: "select".to_string(), params: vec![Type::Int]}){
             report("This function is not allowed in this scope", func_call_tree.kind.location.clone());
             valid = false;
         }
@@ -423,10 +424,17 @@ fn verify_subcanvas(subcanvas_tree: &AST, env: &Environment) -> bool{
     }
 
     if env.has_ctx("in_travel"){
-        report("subcanvas is illegal in this scope", subcanvas_tree.kind.location.clone());
+        report(&format!("{} is illegal in this scope", subcanvas_tree.kind.literal), subcanvas_tree.kind.location.clone());
         valid = false;
     }
 
+    for size in subcanvas_tree.children[2..4].iter(){
+        if size.kind.kind == TokenType::Int && size.kind.literal.starts_with('-'){
+            report("The size of a subcanvas can't be negative", size.kind.location.clone());
+            valid = false;
+        }
+    }
+
     for arg in subcanvas_tree.children[0..4].iter(){
         if !verify_expr(arg, env){
             valid = false;
@@ -480,16 +488,11 @@ fn verify_import(import_tree: &AST, requester:Option<&Location>, env:&mut Enviro
     };
 
 
-    if !script_path.exists(){
+    if !env.host.sources.exists(script_path){
         report(&format!("The path {} doesn't exist", script_path.display()), import_tree.kind.location.clone());
         return false;
     }
 
-    if !script_path.is_file(){
-        report(&format!("The path {} is not a file", script_path.display()), import_tree.kind.location.clone());
-        return false;
-    }
-
     let name = if has_aliasing{
         &import_tree.children[0].children[1].kind.literal
     }else{
@@ -519,11 +522,19 @@ fn verify_import(import_tree: &AST, requester:Option<&Location>, env:&mut Enviro
     }
 
     let valid = if !env.cached_imports.contains_key(script_path){
-        match tokenize(script_path.to_str().unwrap()){
+        let content = match env.host.sources.read(script_path){
+            Ok(content) => content,
+            Err(e) => {
+                report(&e, import_tree.kind.location.clone());
+                return false;
+            }
+        };
+
+        match tokenize(&content, script_path.to_str().unwrap()){
             LexingResult::Ok(forest) => {
                 match crate::parser::parse(&forest, true){
                     Some(forest) => {
-                        let mut other_env = Environment::default();
+                        let mut other_env = Environment::with_host(env.host.clone());
                         let res = verify(&forest, Some(&import_tree.kind.location), &mut other_env);
                         env.cached_imports.insert(script_path.to_path_buf(), forest);
                         env.cached_imports.extend(other_env.cached_imports);
@@ -541,13 +552,13 @@ fn verify_import(import_tree: &AST, requester:Option<&Location>, env:&mut Enviro
     
             LexingResult::Err(errs) => {
                 for e in errs{
-                    eprintln!("{}", e);
+                    report_message(&e.to_string());
                 }
                 false
             }
         }
     }else{
-        let mut other_env = Environment::default();
+        let mut other_env = Environment::with_host(env.host.clone());
         let res = verify(&env.cached_imports[script_path], Some(&import_tree.kind.location), &mut other_env);
         env.cached_imports.extend(other_env.cached_imports);
         env.public_functions.extend(other_env.public_functions);
@@ -567,6 +578,12 @@ fn verify_dot(dot_tree:&AST, env: &Environment) -> bool{
     get_expr_return_type(dot_tree, env).is_some()
 }
 
+/// Checks an expression that is not part of a statement and gives its type
+pub fn verify_expr_type(expr: &AST, env:&Environment) -> Option<Type>{
+    if !verify_expr(expr, env){ return None; }
+    get_expr_return_type(expr, env)
+}
+
 fn verify_expr(expr: &AST, env:&Environment) -> bool{
 
     if expr.kind.kind == TokenType::Dot{
@@ -609,6 +626,8 @@ pub fn get_type(name: String) -> Option<Type>{
         Some(Type::Bool)
     }else if &name == "color"{
         Some(Type::Color)
+    }else if &name == "string"{
+        Some(Type::String)
     }else{ None }
 }
 
@@ -639,6 +658,7 @@ fn get_expr_return_type(expr: &AST, env:&Environment) -> Option<Type>{
             TokenType::Float => Some(Type::Float),
             TokenType::Bool => Some(Type::Bool),
             TokenType::Hex => Some(Type::Color),
+            TokenType::String => Some(Type::String),
 
             _ => panic!("Unexpected operand")
         }
@@ -738,6 +758,10 @@ fn get_expr_return_type(expr: &AST, env:&Environment) -> Option<Type>{
 
                 match (left, right){
                     (Type::Int, Type::Int) => Some(Type::Int),
+                    (Type::Float, Type::Float) => Some(Type::Float),
+
+                    (Type::Int, Type::Float) => Some(Type::Float),
+                    (Type::Float, Type::Int) => Some(Type::Float),
 
                     _ => {
                         report(&format!("Operator '%' is not defined for types '{:?}' and '{:?}'", left, right), expr.kind.location.clone());
@@ -1,19 +1,33 @@
-use std::{collections::HashMap, path::{Path, PathBuf}};
+use std::{collections::HashMap, path::{Path, PathBuf}, time::{SystemTime, UNIX_EPOCH}};
 
-use image::{ImageBuffer, RgbaImage, Rgba, imageops};
+use image::{ImageBuffer, Pixel, Rgba, imageops::{self, FilterType}};
 
-use crate::{ir::{Instruction, Param, Runtime, Script}, to_rgba, from_rgba};
+use crate::{ir::{Instruction, Param, Runtime, Script}, noise::Noise, draw::Pen, blend::BlendMode, text::Font, color::ColorSpace, pixels::{Pixels, Depth, Channel, each_depth, map_depth}, engine::Value, environment::Type, to_rgba, from_rgba};
+
+pub(crate) mod builtins;
+pub(crate) mod debug;
+
+use builtins::{BUILTINS, Args};
+use debug::DebugHook;
+
+/// Largest side a canvas can have
+pub(crate) const MAX_CANVAS_SIZE:i32 = 16384;
 
 
 #[derive(Debug)]
 struct StackFrame{
-    registers: HashMap<String, u32>
+    registers: HashMap<String, u32>,
+    /// Script and label of the code run in the frame
+    script: PathBuf,
+    label: String,
+    /// Line of the statement being run
+    line: usize
 }
 
 
 impl Default for StackFrame{
     fn default() -> Self {
-        StackFrame { registers: HashMap::from_iter([("_rt".to_string(), 0)]) }
+        StackFrame { registers: HashMap::from_iter([("_rt".to_string(), 0)]), script: PathBuf::new(), label: String::new(), line: 0 }
     }
 }
 
@@ -27,42 +41,72 @@ impl StackFrame{
     }
 }
 
+/// How a layer is composited over the canvas it was pushed on
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Layer{
+    pub opacity: f32,
+    pub blend: BlendMode
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct Canvas{
+    pub id: u32,
     pub width: u32,
     pub height: u32,
-    pub data: RgbaImage
+    pub data: Pixels,
+    pub layer: Option<Layer>
     
 }
 
 
 
 impl Canvas{
-    pub fn new(width: u32, height: u32) -> Self{
-        let data = ImageBuffer::new(width, height);
-        Canvas { width, height, data}
+    pub fn new(width: u32, height: u32, depth: Depth) -> Self{
+        let data = Pixels::new(width, height, depth);
+        Canvas { id: 0, width, height, data, layer: None }
     }
 
     pub fn put(&mut self, x:u32, y:u32, pixel:u32){
-        self.data.put_pixel(x, y, Rgba(to_rgba(pixel)));
+        self.data.put(x, y, to_rgba(pixel).map(|c| c.to_unit()));
     }
 
     pub fn get(&self, x:u32, y:u32) -> u32{
-        let [r, g, b, a] = self.data.get_pixel(x, y).0;
+        let [r, g, b, a] = self.data.get(x, y).map(u8::from_unit);
         from_rgba(r, g, b, a)
     }
 
-    pub fn merge(&mut self, offst_x:i32, offst_y:i32, source:Self){
+    /// Overwrites the region starting at (`offst_x`, `offst_y`) with `source`, alpha included
+    pub fn copy_from(&mut self, offst_x:i32, offst_y:i32, source:&Self){
+        for y in 0..source.height as i32{
+            for x in 0..source.width as i32{
+                let (dx, dy) = (offst_x + x, offst_y + y);
+                if dx >= 0 && dy >= 0 && dx < self.width as i32 && dy < self.height as i32{
+                    self.data.put(dx as u32, dy as u32, source.data.get(x as u32, y as u32));
+                }
+            }
+        }
+    }
+
+    /// Composites `pixel` over the current one, its alpha scaled by `coverage`
+    pub fn blend(&mut self, x:u32, y:u32, pixel:u32, coverage:f32, mode:BlendMode){
+        self.blend_unit(x, y, to_rgba(pixel).map(|c| c.to_unit()), coverage, mode);
+    }
+
+    /// Same as `blend` with a color made of normalized channels
+    pub fn blend_unit(&mut self, x:u32, y:u32, color:[f32; 4], coverage:f32, mode:BlendMode){
+        let result = mode.composite(color, self.data.get(x, y), coverage, self.data.depth());
+        self.data.put(x, y, result);
+    }
+
+    pub fn merge(&mut self, offst_x:i32, offst_y:i32, source:&Self, mode:BlendMode, opacity:f32){
         for y in offst_y..(offst_y+source.height as i32){
             for x in offst_x..(offst_x+source.width as i32){
                 if (x >= 0 && x < self.width as i32) && (y >= 0 && y < self.height as i32){
 
-                    let pixel = source.get((x-offst_x) as u32, (y-offst_y) as u32);
+                    let color = source.data.get((x-offst_x) as u32, (y-offst_y) as u32);
                     
-                    let [_, _, _, a] = to_rgba(pixel);
-                    if a != 0{
-                        //TODO: Try blending colors
-                        self.put(x as u32, y as u32, pixel);
+                    if color[3] > 0.0{
+                        self.blend_unit(x as u32, y as u32, color, opacity, mode);
                     }
                 }
             }
@@ -71,39 +115,353 @@ impl Canvas{
 
 
 
-    pub fn resize(&mut self, w:u32, h:u32){
+    pub fn resize(&mut self, w:u32, h:u32, filter:FilterType){
         self.width = w;
         self.height = h;
-        self.data = imageops::resize(&self.data, w, h, imageops::FilterType::Nearest);
+        self.data = map_depth!(&self.data, image => imageops::resize(image, w, h, filter));
+    }
+
+    pub fn flip_h(&mut self){
+        each_depth!(&mut self.data, image => imageops::flip_horizontal_in_place(image));
+    }
+
+    pub fn flip_v(&mut self){
+        each_depth!(&mut self.data, image => imageops::flip_vertical_in_place(image));
+    }
+
+    /// Rotates the canvas clockwise by a quarter turn `turns` times, its width and height are swapped for odd turns
+    pub fn rotate_quarter(&mut self, turns:u32){
+        self.data = match turns % 4{
+            1 => map_depth!(&self.data, image => imageops::rotate90(image)),
+            2 => map_depth!(&self.data, image => imageops::rotate180(image)),
+            3 => map_depth!(&self.data, image => imageops::rotate270(image)),
+            _ => return
+        };
+
+        (self.width, self.height) = self.data.dimensions();
+    }
+
+    /// Rotates the contents clockwise by `degrees` around the center of the canvas, the size of the canvas is kept
+    /// and the uncovered pixels become transparent
+    pub fn rotate(&mut self, degrees:f32){
+        each_depth!(&mut self.data, image => rotate_buffer(image, degrees));
+    }
+
+    /// Keeps only the region of size `w`x`h` starting at (`x`, `y`), clipped to the canvas
+    pub fn crop(&mut self, x:i32, y:i32, w:i32, h:i32){
+        let x0 = x.clamp(0, self.width as i32);
+        let y0 = y.clamp(0, self.height as i32);
+        let x1 = x.saturating_add(w).clamp(x0, self.width as i32);
+        let y1 = y.saturating_add(h).clamp(y0, self.height as i32);
+
+        let (w, h) = ((x1 - x0) as u32, (y1 - y0) as u32);
+        self.data = map_depth!(&self.data, image => imageops::crop_imm(image, x0 as u32, y0 as u32, w, h).to_image());
+        self.width = w;
+        self.height = h;
+    }
+
+    /// Averages every pixel with its neighbours up to `radius` pixels away, horizontally then vertically
+    pub fn box_blur(&mut self, radius:i32){
+        if radius > 0{
+            each_depth!(&mut self.data, image => box_blur_buffer(image, radius));
+        }
+    }
+
+    pub fn gaussian_blur(&mut self, sigma:f32){
+        if sigma > 0.0{
+            self.data = map_depth!(&self.data, image => imageops::blur(image, sigma));
+        }
+    }
+
+    /// Applies a square kernel to the color channels, the kernel is divided by its sum when it is not 0
+    /// and the pixels outside of the canvas are the ones of the closest edge
+    pub fn convolve(&mut self, kernel:&[f32]){
+        each_depth!(&mut self.data, image => convolve_buffer(image, kernel));
+    }
+
+    pub fn grayscale(&mut self){
+        each_depth!(&mut self.data, image => grayscale_buffer(image));
+    }
+
+    pub fn invert(&mut self){
+        each_depth!(&mut self.data, image => imageops::invert(image));
+    }
+
+    /// Multiplies the color channels by `factor`
+    pub fn brightness(&mut self, factor:f32){
+        each_depth!(&mut self.data, image => map_channels(image, |c| c * factor));
+    }
+
+    /// Scales the distance of the color channels to mid gray by `factor`
+    pub fn contrast(&mut self, factor:f32){
+        each_depth!(&mut self.data, image => map_channels(image, |c| (c - 0.5) * factor + 0.5));
+    }
+
+    pub fn hue_rotate(&mut self, degrees:i32){
+        self.data = map_depth!(&self.data, image => imageops::huerotate(image, degrees));
+    }
+}
+
+type Buffer<S> = ImageBuffer<Rgba<S>, Vec<S>>;
+
+fn rotate_buffer<S:Channel>(image:&mut Buffer<S>, degrees:f32) where Rgba<S>: Pixel<Subpixel = S>{
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let (cx, cy) = (image.width() as f32 / 2.0, image.height() as f32 / 2.0);
+    let source = image.clone();
+
+    for (x, y, pixel) in image.enumerate_pixels_mut(){
+        let (dx, dy) = (x as f32 + 0.5 - cx, y as f32 + 0.5 - cy);
+        let sx = cos * dx + sin * dy + cx - 0.5;
+        let sy = -sin * dx + cos * dy + cy - 0.5;
+
+        *pixel = Rgba(sample_bilinear(&source, sx, sy).map(S::from_unit));
+    }
+}
+
+/// Interpolates the four pixels around (`x`, `y`), weighting the colors by their alpha so that
+/// transparent pixels do not darken the edges
+fn sample_bilinear<S:Channel>(image:&Buffer<S>, x:f32, y:f32) -> [f32; 4] where Rgba<S>: Pixel<Subpixel = S>{
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let (x0, y0) = (x0 as i64, y0 as i64);
+
+    let mut color = [0.0f32; 3];
+    let mut alpha = 0.0f32;
+
+    for (px, py, weight) in [(x0, y0, (1.0 - fx) * (1.0 - fy)), (x0 + 1, y0, fx * (1.0 - fy)), (x0, y0 + 1, (1.0 - fx) * fy), (x0 + 1, y0 + 1, fx * fy)]{
+        if px < 0 || py < 0 || px >= image.width() as i64 || py >= image.height() as i64{
+            continue;
+        }
+
+        let [r, g, b, a] = image.get_pixel(px as u32, py as u32).0.map(S::to_unit);
+        let a = a * weight;
+        color[0] += r * a;
+        color[1] += g * a;
+        color[2] += b * a;
+        alpha += a;
+    }
+
+    if alpha <= 0.0{
+        return [0.0; 4];
+    }
+
+    let [r, g, b] = color.map(|c| c / alpha);
+    [r, g, b, alpha]
+}
+
+fn box_blur_buffer<S:Channel>(image:&mut Buffer<S>, radius:i32) where Rgba<S>: Pixel<Subpixel = S>{
+    let (w, h) = (image.width() as i32, image.height() as i32);
+    let size = (2 * radius + 1) as f32;
+
+    for (dx, dy) in [(1, 0), (0, 1)]{
+        let source = image.clone();
+
+        for (x, y, pixel) in image.enumerate_pixels_mut(){
+            let mut sum = [0.0f32; 4];
+
+            for i in -radius..=radius{
+                let sx = (x as i32 + i * dx).clamp(0, w - 1) as u32;
+                let sy = (y as i32 + i * dy).clamp(0, h - 1) as u32;
+
+                for (s, c) in sum.iter_mut().zip(source.get_pixel(sx, sy).0){
+                    *s += c.to_unit();
+                }
+            }
+
+            pixel.0 = sum.map(|s| S::from_unit(s / size));
+        }
+    }
+}
+
+fn convolve_buffer<S:Channel>(image:&mut Buffer<S>, kernel:&[f32]) where Rgba<S>: Pixel<Subpixel = S>{
+    let size = (kernel.len() as f32).sqrt() as i32;
+    let half = size / 2;
+    let (w, h) = (image.width() as i32, image.height() as i32);
+
+    let sum = kernel.iter().sum::<f32>();
+    let divisor = if sum == 0.0 { 1.0 } else { sum };
+
+    let source = image.clone();
+
+    for (x, y, pixel) in image.enumerate_pixels_mut(){
+        let mut total = [0.0f32; 3];
+
+        for (i, k) in kernel.iter().enumerate(){
+            let sx = (x as i32 + i as i32 % size - half).clamp(0, w - 1) as u32;
+            let sy = (y as i32 + i as i32 / size - half).clamp(0, h - 1) as u32;
+            let [r, g, b, _] = source.get_pixel(sx, sy).0.map(S::to_unit);
+
+            total[0] += r * k;
+            total[1] += g * k;
+            total[2] += b * k;
+        }
+
+        for (channel, t) in pixel.0.iter_mut().zip(total){
+            *channel = S::from_unit(t / divisor);
+        }
+    }
+}
+
+fn grayscale_buffer<S:Channel>(image:&mut Buffer<S>) where Rgba<S>: Pixel<Subpixel = S>{
+    let gray = imageops::grayscale_alpha(image);
+    for (x, y, pixel) in image.enumerate_pixels_mut(){
+        let [l, a] = gray.get_pixel(x, y).0;
+        *pixel = Rgba([l, l, l, a]);
+    }
+}
+
+/// Applies `f` to the normalized color channels, the alpha is kept
+fn map_channels<S:Channel>(image:&mut Buffer<S>, f:impl Fn(f32) -> f32) where Rgba<S>: Pixel<Subpixel = S>{
+    for pixel in image.pixels_mut(){
+        for channel in pixel.0.iter_mut().take(3){
+            *channel = S::from_unit(f(channel.to_unit()));
+        }
+    }
+}
+
+/// SplitMix64 generator, small and good enough to make scripts reproducible
+#[derive(Debug, Clone)]
+pub(crate) struct Rng{
+    state: u64
+}
+
+impl Rng{
+    pub(crate) fn new(seed: u32) -> Self{
+        Rng { state: seed as u64 }
+    }
+
+    fn next_u64(&mut self) -> u64{
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    pub(crate) fn next_u32(&mut self) -> u32{
+        (self.next_u64() >> 32) as u32
+    }
+
+    /// Uniform float in [0, 1)
+    fn next_f32(&mut self) -> f32{
+        (self.next_u64() >> 40) as f32 / (1u32 << 24) as f32
+    }
+
+    /// Uniform int in [lo, hi]
+    fn next_range(&mut self, lo: i32, hi: i32) -> i32{
+        let (lo, hi) = if lo <= hi { (lo, hi) } else { (hi, lo) };
+        let range = (hi as i64 - lo as i64 + 1) as u64;
+        let offset = (self.next_u32() as u64 * range) >> 32;
+        (lo as i64 + offset as i64) as i32
     }
 }
 
 #[derive(Debug)]
-pub struct VM{
+pub struct VM<'a>{
     runtime: Runtime,
     prog_counter: usize,
     memory: Vec<StackFrame>,
     canvas: Vec<Canvas>,
     saved_canvas: Vec<Canvas>,
-    path_aliases: HashMap<String, PathBuf>
+    path_aliases: HashMap<String, PathBuf>,
+    rng: Rng,
+    noise: Noise,
+    pen: Pen,
+    font: Font,
+    fonts: HashMap<PathBuf, Font>,
+    next_canvas_id: u32,
+    /// Ids of the open subcanvases with the ones of the canvases they merge into, the innermost last
+    subcanvases: Vec<(u32, u32)>,
+    saved_layers: Vec<Vec<Canvas>>,
+    error: Option<String>,
+    debug: Option<DebugHook<'a>>
 }
 
-impl VM{
+impl<'a> VM<'a>{
     pub fn new(runtime: Runtime) -> Self{
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|e| e.subsec_nanos() ^ e.as_secs() as u32)
+            .unwrap_or_default();
+
         VM{
             runtime,
             prog_counter: 0,
             memory: vec![StackFrame::default()],
             canvas: vec![],
             saved_canvas: vec![],
-            path_aliases: HashMap::new()
+            path_aliases: HashMap::new(),
+            rng: Rng::new(seed),
+            noise: Noise::new(seed),
+            pen: Pen::default(),
+            font: Font::default(),
+            fonts: HashMap::new(),
+            next_canvas_id: 0,
+            subcanvases: vec![],
+            saved_layers: vec![],
+            error: None,
+            debug: None
         }
     }
 
+    pub fn set_seed(&mut self, seed: u32){
+        self.rng = Rng::new(seed);
+        self.noise = Noise::new(seed);
+    }
+
     pub fn get_saved_canvas(&self) -> &[Canvas]{
         &self.saved_canvas
     }
 
+    /// Layers of each saved canvas before they were flattened, from the bottom up.
+    /// Empty for a canvas saved without layers
+    pub fn get_saved_layers(&self) -> &[Vec<Canvas>]{
+        &self.saved_layers
+    }
+
+    /// Binds `args` to the parameters of the function run first
+    pub fn set_args(&mut self, args:&[u32]){
+        for (i, arg) in args.iter().enumerate(){
+            self.memory[0].set(&format!("p{i}"), *arg);
+        }
+    }
+
+    /// Content of a register holding `value`, strings being interned
+    pub fn encode(&mut self, value:&Value) -> u32{
+        match value{
+            Value::Int(i) => *i as u32,
+            Value::Float(f) => f.to_bits(),
+            Value::Bool(b) => *b as u32,
+            Value::Color(c) => *c,
+            Value::String(s) => self.intern(s),
+            Value::Void => 0
+        }
+    }
+
+    /// Reads the content of a register as a value of type `t`
+    fn decode(&self, bits:u32, t:Type) -> Value{
+        match t{
+            Type::Int => Value::Int(bits as i32),
+            Type::Float => Value::Float(f32::from_bits(bits)),
+            Type::Bool => Value::Bool(bits != 0),
+            Type::Color => Value::Color(bits),
+            Type::String => Value::String(self.runtime.strings[bits as usize].clone()),
+            Type::Void => Value::Void
+        }
+    }
+
+    /// Index of `content` in the string pool, added to it if missing
+    pub fn intern(&mut self, content:&str) -> u32{
+        let strings = &mut self.runtime.strings;
+
+        match strings.iter().position(|e| e == content){
+            Some(i) => i as u32,
+            None => {
+                strings.push(content.to_string());
+                strings.len() as u32 - 1
+            }
+        }
+    }
+
     pub fn get_script(&self, path:&Path) -> Option<&Script>{
         self.runtime.scripts.iter().find(|e| &e.path == path)
     }
@@ -115,8 +473,11 @@ impl VM{
         }).expect(&format!("Label {} was not found", label)).0
     }
 
-    pub fn run(&mut self, script_path: &Path, entry_point:&str){
+    pub fn run(&mut self, script_path: &Path, entry_point:&str) -> Result<(), String>{
         self.prog_counter = self.get_indx_of(entry_point, script_path);
+        self.memory[0].script = script_path.to_path_buf();
+        self.memory[0].label = entry_point.to_string();
+
         let script = self.get_script(script_path).expect("msg").clone();
         loop{
             if self.prog_counter >= script.program.len(){
@@ -125,7 +486,136 @@ impl VM{
             if! self.exec(&script){ break; }
             else{ self.prog_counter +=1; }
         }
-        
+
+        match self.error.take(){
+            Some(e) => Err(e),
+            None => Ok(())
+        }
+    }
+
+    /// Runs the code at `entry_point` in the current frame, as the inputs of a REPL session.
+    /// The frames of the calls stopped by an error are dropped, the current one stays on top.
+    /// So are the subcanvases they opened, their canvases staying on the stack as ordinary ones
+    pub fn resume(&mut self, script_path: &Path, entry_point:&str) -> Result<(), String>{
+        let depth = self.memory.len();
+        let subcanvases = self.subcanvases.len();
+        let result = self.run(script_path, entry_point);
+
+        let stopped = self.memory.len() - depth;
+        self.memory.drain(..stopped);
+        self.subcanvases.truncate(subcanvases);
+        result
+    }
+
+    /// Value of the register `name` of the current frame, as a value of type `t`
+    pub fn register(&self, name:&str, t:Type) -> Value{
+        self.decode(self.memory[0].get(name), t)
+    }
+
+    /// Canvases being drawn on, the top of the stack first
+    pub fn canvases(&self) -> &[Canvas]{
+        &self.canvas
+    }
+
+    pub fn runtime_mut(&mut self) -> &mut Runtime{
+        &mut self.runtime
+    }
+
+    /// Stops the execution with a runtime error
+    fn fail(&mut self, msg:String) -> bool{
+        self.debug_error(&msg);
+        self.error = Some(msg);
+        false
+    }
+
+    /// Canvas on top of the stack, an error telling to create one when the stack is empty
+    fn top_canvas(&mut self, builtin:&str) -> Result<&mut Canvas, String>{
+        self.canvas.first_mut().ok_or_else(|| format!("{builtin} has no canvas to work on, call create_canvas first"))
+    }
+
+    /// Errors when called from a subcanvas block, `builtin` being the builtin that can't be
+    fn outside_subcanvas(&self, builtin:&str) -> Result<(), String>{
+        if self.subcanvases.is_empty(){
+            Ok(())
+        }else{
+            Err(format!("{builtin} is not allowed inside a subcanvas, nor in the functions called from it"))
+        }
+    }
+
+    fn push_canvas(&mut self, mut canvas:Canvas){
+        canvas.id = self.next_canvas_id;
+        self.next_canvas_id += 1;
+        self.canvas.insert(0, canvas);
+    }
+
+    /// Number of layers pushed on the canvas at `index` of the stack
+    fn layer_count(&self, index:usize) -> usize{
+        self.canvas[..index].iter().rev().take_while(|e| e.layer.is_some()).count()
+    }
+
+    /// Composites the layers on top of the stack over the canvas they were pushed on, from the bottom up.
+    /// Returns the canvas and its layers as they were before flattening
+    fn flatten_layers(&mut self) -> Vec<Canvas>{
+        let count = self.canvas.iter().take_while(|e| e.layer.is_some()).count();
+        if count == 0 || count == self.canvas.len(){
+            return vec![];
+        }
+
+        let layers = self.canvas.drain(0..count).rev().collect::<Vec<Canvas>>();
+        let mut flattened = vec![self.canvas[0].clone()];
+
+        for layer in layers{
+            if let Some(Layer{ opacity, blend }) = layer.layer{
+                self.canvas[0].merge(0, 0, &layer, blend, opacity);
+            }
+            flattened.push(layer);
+        }
+
+        flattened
+    }
+
+    /// Checks a canvas size given by the script, rejecting negative or oversized ones
+    fn canvas_size(&self, w:i32, h:i32) -> Result<(u32, u32), String>{
+        if !(0..=MAX_CANVAS_SIZE).contains(&w) || !(0..=MAX_CANVAS_SIZE).contains(&h){
+            return Err(format!("Invalid canvas size {}x{}, both sides must be between 0 and {}", w, h, MAX_CANVAS_SIZE));
+        }
+        Ok((w as u32, h as u32))
+    }
+
+    fn value_of(&self, param:&Param) -> u32{
+        match param{
+            Param::Value(v) => *v,
+            Param::Register(reg) => self.memory[0].get(reg)
+        }
+    }
+
+    fn int_of(&self, param:&Param) -> i32{
+        self.value_of(param) as i32
+    }
+
+    /// Position in the stack of the canvas `id`, an error when the canvas is not on the stack
+    fn canvas_index(&self, id:u32) -> Result<usize, String>{
+        self.canvas.iter().position(|e| e.id == id).ok_or_else(|| format!("The canvas {} is not on the canvas stack", id as i32))
+    }
+
+    /// Color space named by the string at `index` of the string pool, sRGB when there is none
+    fn space_of(&self, index:Option<u32>) -> Result<ColorSpace, String>{
+        let Some(index) = index else {
+            return Ok(ColorSpace::Srgb);
+        };
+
+        let name = self.string(index);
+        ColorSpace::from_name(name).ok_or_else(|| format!("Unknown color space '{}', expected one of: {}", name, ColorSpace::NAMES.join(", ")))
+    }
+
+    /// Blend mode named by the string at `index` of the string pool
+    fn blend_mode_of(&self, index:u32) -> Result<BlendMode, String>{
+        let name = self.string(index);
+        BlendMode::from_name(name).ok_or_else(|| format!("Unknown blend mode '{}', expected one of: {}", name, BlendMode::NAMES.join(", ")))
+    }
+
+    fn string(&self, index:u32) -> &str{
+        &self.runtime.strings[index as usize]
     }
 
     fn exec(&mut self, script:&Script) -> bool{
@@ -142,7 +632,9 @@ impl VM{
                     Param::Register(reg) => self.memory[0].get(&reg) as i32
                 };
 
-                let result = left+right;
+                let Some(result) = left.checked_add(right) else {
+                    return self.fail(format!("Integer overflow in {left} + {right}"));
+                };
 
                 self.memory[0].set(&r, result as u32);
                 true
@@ -182,25 +674,15 @@ impl VM{
                 true
             },
 
-            Instruction::Alpha(c, r) => {
-                let color = match c {
-                    Param::Value(v) => v,
-                    Param::Register(reg) => self.memory[0].get(&reg)
-                };
-
-                let [_, _, _, a] = to_rgba(color);
-                self.memory[0].set(&r, a as u32);
-                true
-            },
+            Instruction::Builtin(index, params, r) => {
+                let builtin = &BUILTINS[index];
+                let args = Args{ values: params.iter().map(|e| self.value_of(e)).collect(), script: &script.path };
 
-            Instruction::Blue(c, r) => {
-                let color = match c {
-                    Param::Value(v) => v,
-                    Param::Register(reg) => self.memory[0].get(&reg)
-                };
-
-                let [_, b, _, _] = to_rgba(color);
-                self.memory[0].set(&r, b as u32);
+                match (builtin.run)(self, &args){
+                    Ok(value) if builtin.ret != Type::Void => self.memory[0].set(&r, value),
+                    Ok(_) => {},
+                    Err(e) => return self.fail(e)
+                }
                 true
             },
 
@@ -227,11 +709,13 @@ impl VM{
 
                     let path = old_aliases.get(script).unwrap();
 
-                    self.run(&path, &func_label);
+                    let result = self.run(&path, &func_label);
                     self.path_aliases = old_aliases;
+
+                    if let Err(e) = result{ return self.fail(e); }
                     
-                }else{
-                    self.run(&script.path, &f);
+                }else if let Err(e) = self.run(&script.path, &f){
+                    return self.fail(e);
                 }
 
 
@@ -242,18 +726,6 @@ impl VM{
                 true
             },
 
-            Instruction::Ceil(x, r) => {
-                let x = match x{
-                    Param::Value(v) => f32::from_bits(v),
-                    Param::Register(reg) => f32::from_bits(self.memory[0].get(&reg))
-                };
-
-                let result = x.ceil() as i32;
-
-                self.memory[0].set(&r, result as u32);
-                true
-            },
-
             Instruction::Copy(a, r) => {
                 match a{
                     Param::Value(v) => self.memory[0].set(&r, v),
@@ -265,19 +737,6 @@ impl VM{
                 true
             },
 
-            Instruction::Cos(x, r) => {
-                let x = match x{
-                    Param::Value(v) => f32::from_bits(v),
-
-                    Param::Register(reg) => f32::from_bits(self.memory[0].get(&reg))
-                };
-
-                let result = x.cos();
-
-                self.memory[0].set(&r, result.to_bits());
-                true
-            },
-
             Instruction::Div(a, b, r) => {
                 let left = match a{
                     Param::Value(v) => v as i32,
@@ -289,7 +748,13 @@ impl VM{
                     Param::Register(reg) => self.memory[0].get(&reg) as i32
                 };
 
-                let result = left / right;
+                if right == 0{
+                    return self.fail("Division by zero".to_string());
+                }
+
+                let Some(result) = left.checked_div(right) else {
+                    return self.fail(format!("Integer overflow in {left} / {right}"));
+                };
 
                 self.memory[0].set(&r, result as u32);
 
@@ -331,34 +796,6 @@ impl VM{
                 true
             },
 
-            Instruction::Fill(c) => {
-                let pixel = match c{
-                    Param::Value(v) => v,
-
-                    Param::Register(reg) => self.memory[0].get(&reg)
-                };
-
-                for y in 0..self.canvas[0].height{
-                    for x in 0..self.canvas[0].width{
-                        self.canvas[0].put(x, y, pixel);
-                    }
-                }
-
-                true
-            },
-
-            Instruction::Floor(x, r) => {
-                let x = match x{
-                    Param::Value(v) => f32::from_bits(v),
-                    Param::Register(reg) => f32::from_bits(self.memory[0].get(&reg))
-                };
-
-                let result = x.floor() as i32;
-
-                self.memory[0].set(&r, result as u32);
-                true
-            },
-
             Instruction::Flt(a, r) => {
                 let value = match a{
                     Param::Value(v) => v as i32,
@@ -408,17 +845,6 @@ impl VM{
                 true
             },
 
-            Instruction::Green(c, r) => {
-                let color = match c {
-                    Param::Value(v) => v,
-                    Param::Register(reg) => self.memory[0].get(&reg)
-                };
-
-                let [_, g, _, _] = to_rgba(color);
-                self.memory[0].set(&r, g as u32);
-                true
-            },
-
             Instruction::GT(a, b, r) => {
                 let left = match a{
                     Param::Value(v) => v as i32,
@@ -453,29 +879,11 @@ impl VM{
                 true
             },
 
-            Instruction::Height(r) => {
-                self.memory[0].set(&r, self.canvas[0].height);
-                true
-            },
-
             Instruction::Import(path, name) => {
                 self.path_aliases.insert(name, Path::new(&path).to_path_buf());
                 true
             },
 
-            Instruction::Int(a, r) => {
-                let value = match a{
-                    Param::Value(v) => f32::from_bits(v),
-                    Param::Register(reg) => f32::from_bits(self.memory[0].get(&reg))
-                };
-
-                let result = value as i32;
-
-                self.memory[0].set(&r, result as u32);
-
-                true
-            },
-
             Instruction::JF(a, label) => {
                 let value = match a{
                     Param::Value(v) => v != 0,
@@ -564,7 +972,15 @@ impl VM{
 
             Instruction::Label(_) => {true},
 
-            Instruction::Merge(x, y) => {
+            Instruction::Line(line) => {
+                self.memory[0].line = line;
+                if !self.debug_statement(&script.path, line){
+                    return self.fail("Stopped by the debugger".to_string());
+                }
+                true
+            },
+
+            Instruction::Merge(x, y, copy) => {
                 let x = match x {
                     Param::Value(v) => v as i32,
                     Param::Register(reg) => self.memory[0].get(&reg) as i32
@@ -575,9 +991,23 @@ impl VM{
                     Param::Register(reg) => self.memory[0].get(&reg) as i32
                 };
 
+                self.flatten_layers();
+
+                // The stack must be as the subcanvas left it, whatever the functions called in the block did
+                let stack = (self.canvas.first().map(|e| e.id), self.canvas.get(1).map(|e| e.id));
+                match self.subcanvases.pop(){
+                    Some((sub, parent)) if stack == (Some(sub), Some(parent)) => {},
+                    _ => return self.fail("The canvas stack changed inside the subcanvas, it can't be merged into its parent canvas".to_string())
+                }
+
                 let to_merge = self.canvas.remove(0);
 
-                self.canvas[0].merge(x, y, to_merge);
+                // A copied subcanvas already holds the parent's pixels, it is written back as is
+                if copy{
+                    self.canvas[0].copy_from(x, y, &to_merge);
+                }else{
+                    self.canvas[0].merge(x, y, &to_merge, self.pen.blend, 1.0);
+                }
 
 
                 true
@@ -594,13 +1024,35 @@ impl VM{
                     Param::Register(reg) => self.memory[0].get(&reg) as i32
                 };
 
-                let result = left % right;
+                if right == 0{
+                    return self.fail("Modulo by zero".to_string());
+                }
+
+                let result = left.wrapping_rem(right);
 
                 self.memory[0].set(&r, result as u32);
 
                 true
             },
 
+            Instruction::Modf(a, b, r) => {
+                let left = match a {
+                    Param::Value(v) => f32::from_bits(v),
+                    Param::Register(reg) => f32::from_bits(self.memory[0].get(&reg))
+                };
+
+                let right = match b {
+                    Param::Value(v) => f32::from_bits(v),
+                    Param::Register(reg) => f32::from_bits(self.memory[0].get(&reg))
+                };
+
+                let result = left % right;
+
+                self.memory[0].set(&r, result.to_bits());
+
+                true
+            },
+
             Instruction::Mul(a, b, r) => {
                 let left = match a {
                     Param::Value(v) => v as i32,
@@ -612,7 +1064,9 @@ impl VM{
                     Param::Register(reg) => self.memory[0].get(&reg) as i32
                 };
 
-                let result = left * right;
+                let Some(result) = left.checked_mul(right) else {
+                    return self.fail(format!("Integer overflow in {left} * {right}"));
+                };
 
                 self.memory[0].set(&r, result as u32);
 
@@ -637,6 +1091,28 @@ impl VM{
                 true
             },
 
+            Instruction::Native(sign, params, r) => {
+                let args = params.iter().zip(&sign.params).map(|(p, t)| self.decode(self.value_of(p), *t)).collect::<Vec<Value>>();
+
+                let natives = self.runtime.natives.clone();
+                let Some((ret, func)) = natives.get(&sign) else {
+                    return self.fail(format!("The native function {sign} was not registered"));
+                };
+
+                let value = match func(&args){
+                    Ok(value) => value,
+                    Err(e) => return self.fail(format!("{}: {e}", sign.name))
+                };
+
+                if ret != Type::Void && value.type_of() != ret{
+                    return self.fail(format!("{}: expected a value of type {:?} but got {:?}", sign.name, ret, value));
+                }
+
+                let bits = self.encode(&value);
+                self.memory[0].set(&r, bits);
+                true
+            },
+
             Instruction::NE(a, b, r) => {
                 let left = match a{
                     Param::Value(v) => v,
@@ -660,7 +1136,11 @@ impl VM{
                     Param::Register(reg) => self.memory[0].get(&reg) as i32
                 };
 
-                self.memory[0].set(&r, (-value) as u32);
+                let Some(result) = value.checked_neg() else {
+                    return self.fail(format!("Integer overflow in -({value})"));
+                };
+
+                self.memory[0].set(&r, result as u32);
                 true
             },
 
@@ -701,11 +1181,6 @@ impl VM{
                 true
             },
 
-            Instruction::Pop => {
-                self.canvas.remove(0);
-                true
-            },
-
             Instruction::Pow(a, b, r) => {
                 let left = match a{
                     Param::Value(v) => v as i32,
@@ -720,7 +1195,10 @@ impl VM{
                 let result = if right < 0{
                     (left as f32).powf(right as f32) as i32
                 }else{
-                    left.pow(right as u32)
+                    let Some(result) = left.checked_pow(right as u32) else {
+                        return self.fail(format!("Integer overflow in {left} ^ {right}"));
+                    };
+                    result
                 };
 
                 self.memory[0].set(&r, result as u32);
@@ -744,152 +1222,26 @@ impl VM{
                 true
             },
 
-            Instruction::Push(a, b) => {
-                let mut left = match a{
-                    Param::Value(v) => v as i32,
-                    Param::Register(reg) => self.memory[0].get(&reg) as i32
-                };
-
-                let mut right = match b{
-                    Param::Value(v) => v as i32,
-                    Param::Register(reg) => self.memory[0].get(&reg) as i32
-                };
-
-                if left < 0 {left = 0;}
-                if right < 0 {right = 0;}
-
-                self.canvas.insert(0, Canvas::new(left as u32, right as u32));
-
-                true
-            },
-
-            Instruction::Put(x, y, c) => {
-                let x = match x{
-                    Param::Value(v) => v as i32,
-                    Param::Register(reg) => self.memory[0].get(&reg) as i32
-                };
-
-                let y = match y{
-                    Param::Value(v) => v as i32,
-                    Param::Register(reg) => self.memory[0].get(&reg) as i32
-                };
-
-                let color = match c{
-                    Param::Value(v) => v,
-                    Param::Register(reg) => self.memory[0].get(&reg)
-                };
-
-                let width = self.canvas[0].width;
-                let height = self.canvas[0].height;
-
-                if (0 <= x && x < width as i32) && (0 <= y && y < height as i32){
-                    self.canvas[0].put(x as u32, y as u32, color);
-                }
-
-
-                true
-            },
-
-            Instruction::Red(c, r) => {
-                let color = match c {
-                    Param::Value(v) => v,
-                    Param::Register(reg) => self.memory[0].get(&reg)
-                };
-
-                let [red, _, _, _] = to_rgba(color);
-                self.memory[0].set(&r, red as u32);
-                true
-            },
-
-            Instruction::Resize(w, h) => {
-                let mut w = match w {
-                    Param::Value(v) => v as i32,
-                    Param::Register(reg) => self.memory[0].get(&reg) as i32
-                };
-
-                let mut h = match h {
-                    Param::Value(v) => v as i32,
-                    Param::Register(reg) => self.memory[0].get(&reg) as i32
-                };
-
-                if w < 0 {w = 0;}
-                if h < 0 {h = 0;}
-
-                self.canvas[0].resize(w as u32, h as u32);
-                true
-            },
-
-            Instruction::RGBA(r, g, b, a, reg) => {
-                let mut r = match r{
-                    Param::Value(v) => v as i32,
-                    Param::Register(register) => self.memory[0].get(&register) as i32
-                };
-
-                let mut g = match g{
-                    Param::Value(v) => v as i32,
-                    Param::Register(register) => self.memory[0].get(&register) as i32
-                };
-
-                let mut b = match b{
-                    Param::Value(v) => v as i32,
-                    Param::Register(register) => self.memory[0].get(&register) as i32
-                };
-
-                let mut a = match a{
-                    Param::Value(v) => v as i32,
-                    Param::Register(register) => self.memory[0].get(&register) as i32
-                };
-
-                r = (r.clamp(0, 255) & 0xff) << 24;
-                g = (g.clamp(0, 255) & 0xff) << 16;
-                b = (b.clamp(0, 255) & 0xff) << 8;
-                a = a.clamp(0, 255) & 0xff;
-
-                let color = r | g | b | a;
-
-                self.memory[0].set(&reg, color as u32);
-
-
-                true
-            }
-
             Instruction::Ret => {false},
 
-            Instruction::Sample(x, y, r) => {
-                let x = match x {
-                    Param::Value(v) => v as i32,
-                    Param::Register(reg) => self.memory[0].get(&reg) as i32
-                };
-
-                let y = match y {
-                    Param::Value(v) => v as i32,
-                    Param::Register(reg) => self.memory[0].get(&reg) as i32
-                };
-
-                let width = self.canvas[0].width;
-                let height = self.canvas[0].height;
-
-                if (0 <= x && x < width as i32) && (0 <= y && y < height as i32){
-                    self.memory[0].set(&r, self.canvas[0].get(x as u32, y as u32));
+            Instruction::Subcanvas(x, y, w, h, copy) => {
+                if self.canvas.is_empty(){
+                    return self.fail("subcanvas needs a canvas to draw on, call create_canvas first".to_string());
                 }
 
-                true
-            }
-
-            Instruction::Save => {
-                self.saved_canvas.push(self.canvas[0].clone());
-                true
-            },
-
-            Instruction::Sin(x, r) => {
-                let x = match x{
-                    Param::Value(v) => f32::from_bits(v),
-                    Param::Register(reg) => f32::from_bits(self.memory[0].get(&reg))
+                let (w, h) = match self.canvas_size(self.int_of(&w), self.int_of(&h)){
+                    Ok(size) => size,
+                    Err(e) => return self.fail(e)
                 };
 
-                let result = x.sin();
+                let mut canvas = Canvas::new(w, h, self.canvas[0].data.depth());
+                if copy{
+                    canvas.copy_from(-self.int_of(&x), -self.int_of(&y), &self.canvas[0]);
+                }
 
-                self.memory[0].set(&r, result.to_bits());
+                let parent = self.canvas[0].id;
+                self.push_canvas(canvas);
+                self.subcanvases.push((self.canvas[0].id, parent));
                 true
             },
 
@@ -904,7 +1256,9 @@ impl VM{
                     Param::Register(reg) => self.memory[0].get(&reg) as i32
                 };
 
-                let result = left - right;
+                let Some(result) = left.checked_sub(right) else {
+                    return self.fail(format!("Integer overflow in {left} - {right}"));
+                };
 
                 self.memory[0].set(&r, result as u32);
                 true
@@ -926,11 +1280,6 @@ impl VM{
                 self.memory[0].set(&r, result.to_bits());
                 true
             },
-
-            Instruction::Width(r) => {
-                self.memory[0].set(&r, self.canvas[0].width);
-                true
-            }
         }
     }
 
@@ -0,0 +1,820 @@
+use std::path::Path;
+
+use image::imageops::FilterType;
+
+use crate::{environment::{FuncSign, Type}, vm::{VM, Canvas, Layer}, color, pixels::Depth, blend::BlendMode, text::Font, to_rgba, from_rgba};
+
+use Type::{Int, Float, Color, Bool, String as Str, Void};
+
+/// Implementation of a builtin, returning the raw content of its result. An error stops the script
+type Run = fn(&mut VM, &Args) -> Result<u32, String>;
+
+/// A function every script can call, overloads being separate entries of the same name
+#[derive(Debug)]
+pub struct Builtin{
+    pub name: &'static str,
+    pub params: &'static [Type],
+    pub ret: Type,
+    pub run: Run
+}
+
+impl Builtin{
+    const fn new(name:&'static str, params:&'static [Type], ret:Type, run:Run) -> Self{
+        Builtin{ name, params, ret, run }
+    }
+}
+
+/// Raw content of the arguments of a builtin call, read according to its parameter types
+pub struct Args<'a>{
+    pub values: Vec<u32>,
+    /// Script the call is made from
+    pub script: &'a Path
+}
+
+impl Args<'_>{
+    fn int(&self, i:usize) -> i32{
+        self.values[i] as i32
+    }
+
+    fn float(&self, i:usize) -> f32{
+        f32::from_bits(self.values[i])
+    }
+
+    fn color(&self, i:usize) -> u32{
+        self.values[i]
+    }
+
+    /// Id of a canvas, as given by `create_canvas`
+    fn canvas(&self, i:usize) -> u32{
+        self.values[i]
+    }
+
+    fn bool(&self, i:usize) -> bool{
+        self.values[i] != 0
+    }
+
+    /// Index of a string in the string pool
+    fn string(&self, i:usize) -> u32{
+        self.values[i]
+    }
+
+    /// `None` for an omitted optional parameter
+    fn optional(&self, i:usize) -> Option<u32>{
+        self.values.get(i).copied()
+    }
+}
+
+/// Every builtin function. Adding one only takes a new entry here and its implementation below
+pub const BUILTINS:&[Builtin] = &[
+    Builtin::new("create_canvas", &[Int, Int], Int, create_canvas),
+    Builtin::new("create_canvas", &[Int, Int, Str], Int, create_canvas),
+    Builtin::new("save_canvas", &[], Void, save_canvas),
+    Builtin::new("put", &[Int, Int, Color], Void, put),
+    Builtin::new("put", &[Int, Int, Color, Bool], Void, put_blend),
+    Builtin::new("fill", &[Color], Void, fill),
+    Builtin::new("sample", &[Int, Int], Color, sample),
+    Builtin::new("width", &[], Int, width),
+    Builtin::new("height", &[], Int, height),
+
+    Builtin::new("int", &[Float], Int, int),
+    Builtin::new("float", &[Int], Float, float),
+
+    Builtin::new("red", &[Color], Int, red),
+    Builtin::new("green", &[Color], Int, green),
+    Builtin::new("blue", &[Color], Int, blue),
+    Builtin::new("alpha", &[Color], Int, alpha),
+    Builtin::new("rgba", &[Int, Int, Int, Int], Color, rgba),
+    Builtin::new("rgb", &[Int, Int, Int], Color, rgb),
+
+    Builtin::new("cos", &[Float], Float, cos),
+    Builtin::new("sin", &[Float], Float, sin),
+    Builtin::new("tan", &[Float], Float, tan),
+    Builtin::new("atan2", &[Float, Float], Float, atan2),
+    Builtin::new("sqrt", &[Float], Float, sqrt),
+    Builtin::new("exp", &[Float], Float, exp),
+    Builtin::new("log", &[Float], Float, log),
+    Builtin::new("floor", &[Float], Int, floor),
+    Builtin::new("ceil", &[Float], Int, ceil),
+    Builtin::new("round", &[Float], Int, round),
+    Builtin::new("fract", &[Float], Float, fract),
+    Builtin::new("pi", &[], Float, pi),
+    Builtin::new("abs", &[Int], Int, abs),
+    Builtin::new("abs", &[Float], Float, absf),
+    Builtin::new("sign", &[Int], Int, sign),
+    Builtin::new("sign", &[Float], Float, signf),
+    Builtin::new("min", &[Int, Int], Int, min),
+    Builtin::new("min", &[Float, Float], Float, minf),
+    Builtin::new("max", &[Int, Int], Int, max),
+    Builtin::new("max", &[Float, Float], Float, maxf),
+    Builtin::new("clamp", &[Int, Int, Int], Int, clamp),
+    Builtin::new("clamp", &[Float, Float, Float], Float, clampf),
+    Builtin::new("lerp", &[Float, Float, Float], Float, lerp),
+    Builtin::new("smoothstep", &[Float, Float, Float], Float, smoothstep),
+
+    Builtin::new("seed", &[Int], Void, seed),
+    Builtin::new("rand_int", &[Int, Int], Int, rand_int),
+    Builtin::new("rand_float", &[], Float, rand_float),
+    Builtin::new("rand_color", &[], Color, rand_color),
+    Builtin::new("perlin", &[Float, Float], Float, perlin),
+    Builtin::new("simplex", &[Float, Float], Float, simplex),
+    Builtin::new("worley", &[Float, Float], Float, worley),
+    Builtin::new("fbm", &[Float, Float, Int], Float, fbm),
+
+    Builtin::new("line", &[Int, Int, Int, Int, Color], Void, line),
+    Builtin::new("rect", &[Int, Int, Int, Int, Color], Void, rect),
+    Builtin::new("fill_rect", &[Int, Int, Int, Int, Color], Void, fill_rect),
+    Builtin::new("circle", &[Int, Int, Int, Color], Void, circle),
+    Builtin::new("fill_circle", &[Int, Int, Int, Color], Void, fill_circle),
+    Builtin::new("ellipse", &[Int, Int, Int, Int, Color], Void, ellipse),
+    Builtin::new("fill_ellipse", &[Int, Int, Int, Int, Color], Void, fill_ellipse),
+    Builtin::new("triangle", &[Int, Int, Int, Int, Int, Int, Color], Void, triangle),
+    Builtin::new("fill_triangle", &[Int, Int, Int, Int, Int, Int, Color], Void, fill_triangle),
+    Builtin::new("set_antialias", &[Bool], Void, set_antialias),
+    Builtin::new("set_stroke", &[Int], Void, set_stroke),
+    Builtin::new("blend_mode", &[Str], Void, blend_mode),
+
+    Builtin::new("text", &[Int, Int, Str, Color, Int], Void, text),
+    Builtin::new("text_width", &[Str, Int], Int, text_width),
+    Builtin::new("load_font", &[Str], Void, load_font),
+    Builtin::new("default_font", &[], Void, default_font),
+
+    Builtin::new("blur", &[Int], Void, blur),
+    Builtin::new("gaussian_blur", &[Float], Void, gaussian_blur),
+    Builtin::new("sharpen", &[], Void, sharpen),
+    Builtin::new("grayscale", &[], Void, grayscale),
+    Builtin::new("invert", &[], Void, invert),
+    Builtin::new("brightness", &[Float], Void, brightness),
+    Builtin::new("contrast", &[Float], Void, contrast),
+    Builtin::new("hue_rotate", &[Int], Void, hue_rotate),
+    Builtin::new("convolve", &[Str], Void, convolve),
+
+    Builtin::new("flip_h", &[], Void, flip_h),
+    Builtin::new("flip_v", &[], Void, flip_v),
+    Builtin::new("rotate90", &[], Void, rotate90),
+    Builtin::new("rotate180", &[], Void, rotate180),
+    Builtin::new("rotate270", &[], Void, rotate270),
+    Builtin::new("rotate", &[Float], Void, rotate),
+    Builtin::new("crop", &[Int, Int, Int, Int], Void, crop),
+    Builtin::new("resize", &[Int, Int], Void, resize),
+    Builtin::new("resize", &[Int, Int, Str], Void, resize_filter),
+
+    Builtin::new("sample_from", &[Int, Int, Int], Color, sample_from),
+    Builtin::new("put_to", &[Int, Int, Int, Color], Void, put_to),
+    Builtin::new("select", &[Int], Void, select),
+    Builtin::new("copy", &[Int, Int, Int, Int], Void, copy),
+
+    Builtin::new("layer_push", &[Float, Str], Void, layer_push),
+    Builtin::new("layer_pop", &[], Void, layer_pop),
+
+    Builtin::new("hsv", &[Float, Float, Float], Color, hsv),
+    Builtin::new("hsl", &[Float, Float, Float], Color, hsl),
+    Builtin::new("to_hue", &[Color], Float, to_hue),
+    Builtin::new("to_saturation", &[Color], Float, to_saturation),
+    Builtin::new("to_lightness", &[Color], Float, to_lightness),
+    Builtin::new("mix", &[Color, Color, Float], Color, mix),
+    Builtin::new("mix", &[Color, Color, Float, Str], Color, mix),
+    Builtin::new("gradient_linear", &[Int, Int, Int, Int, Color, Color], Void, gradient_linear),
+    Builtin::new("gradient_linear", &[Int, Int, Int, Int, Color, Color, Str], Void, gradient_linear),
+    Builtin::new("gradient_radial", &[Int, Int, Int, Color, Color], Void, gradient_radial),
+    Builtin::new("gradient_radial", &[Int, Int, Int, Color, Color, Str], Void, gradient_radial),
+
+    Builtin::new("put_float", &[Int, Int, Float, Float, Float, Float], Void, put_float),
+    Builtin::new("sample_float", &[Int, Int, Str], Float, sample_float)
+];
+
+/// Index in `BUILTINS` of the builtin of that signature
+pub fn find(sign:&FuncSign) -> Option<usize>{
+    BUILTINS.iter().position(|e| e.name == sign.name && e.params == sign.params)
+}
+
+
+fn create_canvas(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    let (w, h) = vm.canvas_size(args.int(0), args.int(1))?;
+
+    let depth = match args.optional(2).map(|e| vm.string(e)){
+        Some(name) => Depth::from_name(name).ok_or_else(|| format!("Unknown canvas depth '{}', expected one of: {}", name, Depth::NAMES.join(", ")))?,
+        None => Depth::Rgba8
+    };
+
+    vm.push_canvas(Canvas::new(w, h, depth));
+    Ok(vm.canvas[0].id)
+}
+
+fn save_canvas(vm:&mut VM, _:&Args) -> Result<u32, String>{
+    vm.top_canvas("save_canvas")?;
+    let layers = vm.flatten_layers();
+    vm.saved_canvas.push(vm.canvas[0].clone());
+    vm.saved_layers.push(layers);
+    vm.canvas.remove(0);
+    Ok(0)
+}
+
+fn put(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    let blend = vm.pen.blend;
+    vm.top_canvas("put")?.plot(args.int(0), args.int(1), args.color(2), 1.0, blend);
+    Ok(0)
+}
+
+/// Without blending, the color is written as it is
+fn put_blend(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    let blend = if args.bool(3) { vm.pen.blend } else { BlendMode::Replace };
+    vm.top_canvas("put")?.plot(args.int(0), args.int(1), args.color(2), 1.0, blend);
+    Ok(0)
+}
+
+fn fill(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    let (pixel, blend) = (args.color(0), vm.pen.blend);
+    let canvas = vm.top_canvas("fill")?;
+
+    for y in 0..canvas.height{
+        for x in 0..canvas.width{
+            canvas.blend(x, y, pixel, 1.0, blend);
+        }
+    }
+    Ok(0)
+}
+
+/// Pixels outside of the canvas are left unread, the result is then 0
+fn sample(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    let (x, y) = (args.int(0), args.int(1));
+    let canvas = vm.top_canvas("sample")?;
+
+    if (0 <= x && x < canvas.width as i32) && (0 <= y && y < canvas.height as i32){
+        return Ok(canvas.get(x as u32, y as u32));
+    }
+    Ok(0)
+}
+
+fn width(vm:&mut VM, _:&Args) -> Result<u32, String>{
+    Ok(vm.top_canvas("width")?.width)
+}
+
+fn height(vm:&mut VM, _:&Args) -> Result<u32, String>{
+    Ok(vm.top_canvas("height")?.height)
+}
+
+
+fn int(_:&mut VM, args:&Args) -> Result<u32, String>{
+    Ok(args.float(0) as i32 as u32)
+}
+
+fn float(_:&mut VM, args:&Args) -> Result<u32, String>{
+    Ok((args.int(0) as f32).to_bits())
+}
+
+
+fn red(_:&mut VM, args:&Args) -> Result<u32, String>{
+    Ok(to_rgba(args.color(0))[0] as u32)
+}
+
+fn green(_:&mut VM, args:&Args) -> Result<u32, String>{
+    Ok(to_rgba(args.color(0))[1] as u32)
+}
+
+fn blue(_:&mut VM, args:&Args) -> Result<u32, String>{
+    Ok(to_rgba(args.color(0))[2] as u32)
+}
+
+fn alpha(_:&mut VM, args:&Args) -> Result<u32, String>{
+    Ok(to_rgba(args.color(0))[3] as u32)
+}
+
+fn rgba(_:&mut VM, args:&Args) -> Result<u32, String>{
+    let [r, g, b, a] = [0, 1, 2, 3].map(|i| args.int(i).clamp(0, 255) as u8);
+    Ok(from_rgba(r, g, b, a))
+}
+
+fn rgb(_:&mut VM, args:&Args) -> Result<u32, String>{
+    let [r, g, b] = [0, 1, 2].map(|i| args.int(i).clamp(0, 255) as u8);
+    Ok(from_rgba(r, g, b, 255))
+}
+
+
+fn cos(_:&mut VM, args:&Args) -> Result<u32, String>{
+    Ok(args.float(0).cos().to_bits())
+}
+
+fn sin(_:&mut VM, args:&Args) -> Result<u32, String>{
+    Ok(args.float(0).sin().to_bits())
+}
+
+fn tan(_:&mut VM, args:&Args) -> Result<u32, String>{
+    Ok(args.float(0).tan().to_bits())
+}
+
+fn atan2(_:&mut VM, args:&Args) -> Result<u32, String>{
+    Ok(args.float(0).atan2(args.float(1)).to_bits())
+}
+
+fn sqrt(_:&mut VM, args:&Args) -> Result<u32, String>{
+    Ok(args.float(0).sqrt().to_bits())
+}
+
+fn exp(_:&mut VM, args:&Args) -> Result<u32, String>{
+    Ok(args.float(0).exp().to_bits())
+}
+
+fn log(_:&mut VM, args:&Args) -> Result<u32, String>{
+    Ok(args.float(0).ln().to_bits())
+}
+
+fn floor(_:&mut VM, args:&Args) -> Result<u32, String>{
+    Ok(args.float(0).floor() as i32 as u32)
+}
+
+fn ceil(_:&mut VM, args:&Args) -> Result<u32, String>{
+    Ok(args.float(0).ceil() as i32 as u32)
+}
+
+fn round(_:&mut VM, args:&Args) -> Result<u32, String>{
+    Ok(args.float(0).round() as i32 as u32)
+}
+
+fn fract(_:&mut VM, args:&Args) -> Result<u32, String>{
+    let x = args.float(0);
+    Ok((x - x.floor()).to_bits())
+}
+
+fn pi(_:&mut VM, _:&Args) -> Result<u32, String>{
+    Ok(std::f32::consts::PI.to_bits())
+}
+
+fn abs(_:&mut VM, args:&Args) -> Result<u32, String>{
+    Ok(args.int(0).wrapping_abs() as u32)
+}
+
+fn absf(_:&mut VM, args:&Args) -> Result<u32, String>{
+    Ok(args.float(0).abs().to_bits())
+}
+
+fn sign(_:&mut VM, args:&Args) -> Result<u32, String>{
+    Ok(args.int(0).signum() as u32)
+}
+
+fn signf(_:&mut VM, args:&Args) -> Result<u32, String>{
+    let value = args.float(0);
+
+    // f32::signum gives 1.0 for 0.0, keep it consistent with the int version
+    let result = if value == 0.0 { 0.0 } else { value.signum() };
+    Ok(result.to_bits())
+}
+
+fn min(_:&mut VM, args:&Args) -> Result<u32, String>{
+    Ok(args.int(0).min(args.int(1)) as u32)
+}
+
+fn minf(_:&mut VM, args:&Args) -> Result<u32, String>{
+    Ok(args.float(0).min(args.float(1)).to_bits())
+}
+
+fn max(_:&mut VM, args:&Args) -> Result<u32, String>{
+    Ok(args.int(0).max(args.int(1)) as u32)
+}
+
+fn maxf(_:&mut VM, args:&Args) -> Result<u32, String>{
+    Ok(args.float(0).max(args.float(1)).to_bits())
+}
+
+fn clamp(_:&mut VM, args:&Args) -> Result<u32, String>{
+    // Not using i32::clamp, it panics when lo > hi
+    Ok(args.int(0).max(args.int(1)).min(args.int(2)) as u32)
+}
+
+fn clampf(_:&mut VM, args:&Args) -> Result<u32, String>{
+    Ok(args.float(0).max(args.float(1)).min(args.float(2)).to_bits())
+}
+
+fn lerp(_:&mut VM, args:&Args) -> Result<u32, String>{
+    let (a, b, t) = (args.float(0), args.float(1), args.float(2));
+    Ok((a + (b - a) * t).to_bits())
+}
+
+fn smoothstep(_:&mut VM, args:&Args) -> Result<u32, String>{
+    let (e0, e1, x) = (args.float(0), args.float(1), args.float(2));
+
+    let result = if e0 == e1{
+        if x < e0 { 0.0 } else { 1.0 }
+    }else{
+        let t = ((x - e0) / (e1 - e0)).clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    };
+    Ok(result.to_bits())
+}
+
+
+fn seed(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    vm.set_seed(args.int(0) as u32);
+    Ok(0)
+}
+
+fn rand_int(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    Ok(vm.rng.next_range(args.int(0), args.int(1)) as u32)
+}
+
+fn rand_float(vm:&mut VM, _:&Args) -> Result<u32, String>{
+    Ok(vm.rng.next_f32().to_bits())
+}
+
+fn rand_color(vm:&mut VM, _:&Args) -> Result<u32, String>{
+    let [red, green, blue, _] = vm.rng.next_u32().to_be_bytes();
+    Ok(from_rgba(red, green, blue, 255))
+}
+
+fn perlin(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    Ok(vm.noise.perlin(args.float(0), args.float(1)).to_bits())
+}
+
+fn simplex(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    Ok(vm.noise.simplex(args.float(0), args.float(1)).to_bits())
+}
+
+fn worley(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    Ok(vm.noise.worley(args.float(0), args.float(1)).to_bits())
+}
+
+fn fbm(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    Ok(vm.noise.fbm(args.float(0), args.float(1), args.int(2)).to_bits())
+}
+
+
+fn line(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    let [x0, y0, x1, y1] = [0, 1, 2, 3].map(|i| args.int(i));
+    let pen = vm.pen;
+    vm.top_canvas("line")?.draw_line(x0, y0, x1, y1, args.color(4), &pen);
+    Ok(0)
+}
+
+fn rect(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    let [x, y, w, h] = [0, 1, 2, 3].map(|i| args.int(i));
+    let pen = vm.pen;
+    vm.top_canvas("rect")?.draw_rect(x, y, w, h, args.color(4), &pen);
+    Ok(0)
+}
+
+fn fill_rect(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    let [x, y, w, h] = [0, 1, 2, 3].map(|i| args.int(i));
+    let pen = vm.pen;
+    vm.top_canvas("fill_rect")?.fill_rect(x, y, w, h, args.color(4), &pen);
+    Ok(0)
+}
+
+fn circle(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    let [x, y, radius] = [0, 1, 2].map(|i| args.int(i));
+    let pen = vm.pen;
+    vm.top_canvas("circle")?.draw_circle(x, y, radius, args.color(3), &pen);
+    Ok(0)
+}
+
+fn fill_circle(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    let [x, y, radius] = [0, 1, 2].map(|i| args.int(i));
+    let pen = vm.pen;
+    vm.top_canvas("fill_circle")?.fill_circle(x, y, radius, args.color(3), &pen);
+    Ok(0)
+}
+
+fn ellipse(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    let [x, y, rx, ry] = [0, 1, 2, 3].map(|i| args.int(i));
+    let pen = vm.pen;
+    vm.top_canvas("ellipse")?.draw_ellipse(x, y, rx, ry, args.color(4), &pen);
+    Ok(0)
+}
+
+fn fill_ellipse(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    let [x, y, rx, ry] = [0, 1, 2, 3].map(|i| args.int(i));
+    let pen = vm.pen;
+    vm.top_canvas("fill_ellipse")?.fill_ellipse(x, y, rx, ry, args.color(4), &pen);
+    Ok(0)
+}
+
+fn triangle(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    let [x0, y0, x1, y1, x2, y2] = [0, 1, 2, 3, 4, 5].map(|i| args.int(i));
+    let pen = vm.pen;
+    vm.top_canvas("triangle")?.draw_triangle(x0, y0, x1, y1, x2, y2, args.color(6), &pen);
+    Ok(0)
+}
+
+fn fill_triangle(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    let [x0, y0, x1, y1, x2, y2] = [0, 1, 2, 3, 4, 5].map(|i| args.int(i));
+    let pen = vm.pen;
+    vm.top_canvas("fill_triangle")?.fill_triangle(x0, y0, x1, y1, x2, y2, args.color(6), &pen);
+    Ok(0)
+}
+
+fn set_antialias(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    vm.pen.antialias = args.bool(0);
+    Ok(0)
+}
+
+fn set_stroke(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    vm.pen.stroke = args.int(0).max(1) as u32;
+    Ok(0)
+}
+
+fn blend_mode(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    vm.pen.blend = vm.blend_mode_of(args.string(0))?;
+    Ok(0)
+}
+
+
+fn text(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    let (x, y, size) = (args.int(0), args.int(1), args.int(4));
+    let (text, font, pen) = (vm.string(args.string(2)).to_string(), vm.font.clone(), vm.pen);
+
+    vm.top_canvas("text")?.draw_text(x, y, &text, args.color(3), size, &font, &pen);
+    Ok(0)
+}
+
+fn text_width(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    Ok(vm.font.text_width(vm.string(args.string(0)), args.int(1)) as u32)
+}
+
+/// Relative paths start from the folder of the script making the call
+fn load_font(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    let name = vm.string(args.string(0));
+    let path = match args.script.parent(){
+        _ if Path::new(name).is_absolute() => Path::new(name).to_path_buf(),
+        Some(dir) => dir.join(name),
+        None => return Err(format!("Unable to load font {}: the script {} has no directory to resolve it against", name, args.script.display()))
+    };
+
+    if let Some(font) = vm.fonts.get(&path){
+        vm.font = font.clone();
+    }else{
+        let data = vm.runtime.sources.read_bytes(&path).map_err(|e| format!("Unable to load font {}: {}", path.display(), e))?;
+        let font = Font::parse(data).map_err(|e| format!("Unable to load font {}: {}", path.display(), e))?;
+        vm.fonts.insert(path, font.clone());
+        vm.font = font;
+    }
+    Ok(0)
+}
+
+fn default_font(vm:&mut VM, _:&Args) -> Result<u32, String>{
+    vm.font = Font::Bitmap;
+    Ok(0)
+}
+
+
+fn blur(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    vm.top_canvas("blur")?.box_blur(args.int(0));
+    Ok(0)
+}
+
+fn gaussian_blur(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    vm.top_canvas("gaussian_blur")?.gaussian_blur(args.float(0));
+    Ok(0)
+}
+
+fn sharpen(vm:&mut VM, _:&Args) -> Result<u32, String>{
+    vm.top_canvas("sharpen")?.convolve(&[0.0, -1.0, 0.0, -1.0, 5.0, -1.0, 0.0, -1.0, 0.0]);
+    Ok(0)
+}
+
+fn grayscale(vm:&mut VM, _:&Args) -> Result<u32, String>{
+    vm.top_canvas("grayscale")?.grayscale();
+    Ok(0)
+}
+
+fn invert(vm:&mut VM, _:&Args) -> Result<u32, String>{
+    vm.top_canvas("invert")?.invert();
+    Ok(0)
+}
+
+fn brightness(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    vm.top_canvas("brightness")?.brightness(args.float(0));
+    Ok(0)
+}
+
+fn contrast(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    vm.top_canvas("contrast")?.contrast(args.float(0));
+    Ok(0)
+}
+
+fn hue_rotate(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    vm.top_canvas("hue_rotate")?.hue_rotate(args.int(0));
+    Ok(0)
+}
+
+fn convolve(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    let text = vm.string(args.string(0));
+    let kernel = text
+        .split(|c:char| c == ',' || c.is_whitespace())
+        .filter(|e| !e.is_empty())
+        .map(|e| e.parse::<f32>())
+        .collect::<Result<Vec<f32>, _>>();
+
+    match kernel{
+        Ok(kernel) if kernel.len() == 9 || kernel.len() == 25 => vm.top_canvas("convolve")?.convolve(&kernel),
+        _ => return Err(format!("Invalid kernel '{}', expected 9 or 25 numbers", text))
+    }
+    Ok(0)
+}
+
+
+fn flip_h(vm:&mut VM, _:&Args) -> Result<u32, String>{
+    vm.top_canvas("flip_h")?.flip_h();
+    Ok(0)
+}
+
+fn flip_v(vm:&mut VM, _:&Args) -> Result<u32, String>{
+    vm.top_canvas("flip_v")?.flip_v();
+    Ok(0)
+}
+
+fn rotate90(vm:&mut VM, _:&Args) -> Result<u32, String>{
+    vm.top_canvas("rotate90")?.rotate_quarter(1);
+    Ok(0)
+}
+
+fn rotate180(vm:&mut VM, _:&Args) -> Result<u32, String>{
+    vm.top_canvas("rotate180")?.rotate_quarter(2);
+    Ok(0)
+}
+
+fn rotate270(vm:&mut VM, _:&Args) -> Result<u32, String>{
+    vm.top_canvas("rotate270")?.rotate_quarter(3);
+    Ok(0)
+}
+
+fn rotate(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    vm.top_canvas("rotate")?.rotate(args.float(0));
+    Ok(0)
+}
+
+fn crop(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    let [x, y, w, h] = [0, 1, 2, 3].map(|i| args.int(i));
+    vm.top_canvas("crop")?.crop(x, y, w, h);
+    Ok(0)
+}
+
+fn resize(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    let (w, h) = vm.canvas_size(args.int(0), args.int(1))?;
+    vm.top_canvas("resize")?.resize(w, h, FilterType::Nearest);
+    Ok(0)
+}
+
+fn resize_filter(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    let (w, h) = vm.canvas_size(args.int(0), args.int(1))?;
+    let name = vm.string(args.string(2));
+    let Some(filter) = filter_of(name) else {
+        return Err(format!("Unknown filter '{}', expected one of: nearest, bilinear, catmull-rom, gaussian, lanczos3", name));
+    };
+
+    vm.top_canvas("resize")?.resize(w, h, filter);
+    Ok(0)
+}
+
+fn filter_of(name:&str) -> Option<FilterType>{
+    match name{
+        "nearest" => Some(FilterType::Nearest),
+        "bilinear" => Some(FilterType::Triangle),
+        "catmull-rom" => Some(FilterType::CatmullRom),
+        "gaussian" => Some(FilterType::Gaussian),
+        "lanczos3" => Some(FilterType::Lanczos3),
+        _ => None
+    }
+}
+
+
+fn sample_from(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    let (x, y) = (args.int(1), args.int(2));
+    let canvas = &vm.canvas[vm.canvas_index(args.canvas(0))?];
+
+    if (0 <= x && x < canvas.width as i32) && (0 <= y && y < canvas.height as i32){
+        return Ok(canvas.get(x as u32, y as u32));
+    }
+    Ok(0)
+}
+
+fn put_to(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    let index = vm.canvas_index(args.canvas(0))?;
+    let blend = vm.pen.blend;
+    vm.canvas[index].plot(args.int(1), args.int(2), args.color(3), 1.0, blend);
+    Ok(0)
+}
+
+fn select(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    vm.outside_subcanvas("select")?;
+    let index = vm.canvas_index(args.canvas(0))?;
+    if vm.canvas[0].layer.is_some() || vm.canvas[index].layer.is_some() || vm.layer_count(index) > 0{
+        return Err("select can't move a canvas with pushed layers, pop them first".to_string());
+    }
+
+    let canvas = vm.canvas.remove(index);
+    vm.canvas.insert(0, canvas);
+    Ok(0)
+}
+
+fn copy(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    let (x, y) = (args.int(2), args.int(3));
+    let src = vm.canvas_index(args.canvas(0))?;
+    let dst = vm.canvas_index(args.canvas(1))?;
+
+    let source = vm.canvas[src].clone();
+    vm.canvas[dst].copy_from(x, y, &source);
+    Ok(0)
+}
+
+
+fn layer_push(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    if vm.canvas.is_empty(){
+        return Err("layer_push needs a canvas to push the layer on, call create_canvas first".to_string());
+    }
+
+    let opacity = args.float(0).clamp(0.0, 1.0);
+    let blend = vm.blend_mode_of(args.string(1))?;
+    if blend == BlendMode::Replace{
+        return Err("A layer can't use the replace blend mode, it would hide the canvas below it".to_string());
+    }
+
+    let mut layer = Canvas::new(vm.canvas[0].width, vm.canvas[0].height, vm.canvas[0].data.depth());
+    layer.layer = Some(Layer{ opacity, blend });
+    vm.push_canvas(layer);
+    Ok(0)
+}
+
+fn layer_pop(vm:&mut VM, _:&Args) -> Result<u32, String>{
+    if vm.canvas.len() < 2 || vm.canvas[0].layer.is_none(){
+        return Err("layer_pop has no layer to pop, call layer_push first".to_string());
+    }
+
+    let layer = vm.canvas.remove(0);
+    if let Some(Layer{ opacity, blend }) = layer.layer{
+        vm.canvas[0].merge(0, 0, &layer, blend, opacity);
+    }
+    Ok(0)
+}
+
+
+fn hsv(_:&mut VM, args:&Args) -> Result<u32, String>{
+    Ok(color::hsv(args.float(0), args.float(1), args.float(2)))
+}
+
+fn hsl(_:&mut VM, args:&Args) -> Result<u32, String>{
+    Ok(color::hsl(args.float(0), args.float(1), args.float(2)))
+}
+
+fn to_hue(_:&mut VM, args:&Args) -> Result<u32, String>{
+    Ok(color::hue(args.color(0)).to_bits())
+}
+
+fn to_saturation(_:&mut VM, args:&Args) -> Result<u32, String>{
+    Ok(color::saturation(args.color(0)).to_bits())
+}
+
+fn to_lightness(_:&mut VM, args:&Args) -> Result<u32, String>{
+    Ok(color::lightness(args.color(0)).to_bits())
+}
+
+/// The color space is optional, sRGB by default
+fn mix(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    let space = vm.space_of(args.optional(3))?;
+    Ok(color::mix(args.color(0), args.color(1), args.float(2), space))
+}
+
+fn gradient_linear(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    let [x0, y0, x1, y1] = [0, 1, 2, 3].map(|i| args.int(i));
+    let (space, blend) = (vm.space_of(args.optional(6))?, vm.pen.blend);
+
+    vm.top_canvas("gradient_linear")?.gradient_linear(x0, y0, x1, y1, args.color(4), args.color(5), space, blend);
+    Ok(0)
+}
+
+fn gradient_radial(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    let [x, y, radius] = [0, 1, 2].map(|i| args.int(i));
+    let (space, blend) = (vm.space_of(args.optional(5))?, vm.pen.blend);
+
+    vm.top_canvas("gradient_radial")?.gradient_radial(x, y, radius, args.color(3), args.color(4), space, blend);
+    Ok(0)
+}
+
+
+fn put_float(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    let (x, y) = (args.int(0), args.int(1));
+    let color = [2, 3, 4, 5].map(|i| args.float(i));
+    let canvas = vm.top_canvas("put_float")?;
+
+    if x >= 0 && y >= 0 && x < canvas.width as i32 && y < canvas.height as i32{
+        canvas.data.put(x as u32, y as u32, color);
+    }
+    Ok(0)
+}
+
+fn sample_float(vm:&mut VM, args:&Args) -> Result<u32, String>{
+    let (x, y) = (args.int(0), args.int(1));
+    let canvas = vm.top_canvas("sample_float")?;
+
+    let color = if x >= 0 && y >= 0 && x < canvas.width as i32 && y < canvas.height as i32{
+        canvas.data.get(x as u32, y as u32)
+    }else{
+        [0.0; 4]
+    };
+
+    let value = match vm.string(args.string(2)){
+        "red" => color[0],
+        "green" => color[1],
+        "blue" => color[2],
+        "alpha" => color[3],
+        name => return Err(format!("Unknown channel '{}', expected one of: red, green, blue, alpha", name))
+    };
+    Ok(value.to_bits())
+}
@@ -0,0 +1,154 @@
+use std::path::{Path, PathBuf};
+
+use crate::{vm::{VM, Canvas}, engine::Value};
+
+/// Drives a script run with `Program::debug`, stopping it before some of its statements
+pub trait Debugger{
+    /// Whether the script stops before running the statement at `line` of the script at `path`
+    fn breakpoint(&self, path:&Path, line:usize) -> bool;
+
+    /// The script stopped for `reason`, returns how it goes on.
+    /// After a runtime error, the script ends whatever is returned
+    fn stopped(&mut self, paused:&Paused, reason:Stop) -> Resume;
+}
+
+/// Why a script stopped
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stop{
+    /// First statement of the script
+    Entry,
+    Breakpoint,
+    /// End of a step
+    Step,
+    /// A runtime error, the script stops where it happened
+    Error(String)
+}
+
+/// How a stopped script goes on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resume{
+    /// Runs until a breakpoint
+    Continue,
+    /// Runs until the next statement of the same function, or of the one it returns to
+    StepOver,
+    /// Runs until the next statement, entering the functions it calls
+    StepInto,
+    /// Runs until the function returns
+    StepOut,
+    /// Ends the script with a runtime error
+    Terminate
+}
+
+/// State of a stopped script
+#[derive(Debug)]
+pub struct Paused<'a>{
+    /// Call stack, the function being run first
+    pub frames: Vec<Frame>,
+    /// Canvases being drawn on, the top of the stack first
+    pub canvases: &'a [Canvas]
+}
+
+/// A function being run
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame{
+    pub function: String,
+    pub script: PathBuf,
+    /// Line of the statement being run
+    pub line: usize,
+    /// Variables and parameters holding a value, by their name in the script
+    pub locals: Vec<(String, Value)>
+}
+
+/// Debugger of a running VM, with the way it was told to go on
+pub(crate) struct DebugHook<'a>{
+    debugger: &'a mut dyn Debugger,
+    resume: Resume,
+    /// The script did not reach its first statement yet, and stops there
+    stop_on_entry: bool,
+    /// Number of frames when the script last stopped
+    depth: usize,
+    /// A runtime error was already given to the debugger
+    failed: bool
+}
+
+impl std::fmt::Debug for DebugHook<'_>{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DebugHook").field("resume", &self.resume).field("depth", &self.depth).finish()
+    }
+}
+
+impl<'a> VM<'a>{
+    /// Stops the script at the statements `debugger` asks for, and before the first one if `stop_on_entry` is set
+    pub fn set_debugger(&mut self, debugger:&'a mut dyn Debugger, stop_on_entry:bool){
+        self.debug = Some(DebugHook{ debugger, resume: Resume::Continue, stop_on_entry, depth: 1, failed: false });
+    }
+
+    /// Called before the statement at `line` of the script at `path`, false if the debugger ends the script
+    pub(crate) fn debug_statement(&mut self, path:&Path, line:usize) -> bool{
+        let Some(mut hook) = self.debug.take() else {
+            return true;
+        };
+
+        let depth = self.memory.len();
+        let reason = if hook.stop_on_entry{
+            hook.stop_on_entry = false;
+            Some(Stop::Entry)
+        }else if hook.debugger.breakpoint(path, line){
+            Some(Stop::Breakpoint)
+        }else{
+            let step_done = match hook.resume{
+                Resume::Continue | Resume::Terminate => false,
+                Resume::StepInto => true,
+                Resume::StepOver => depth <= hook.depth,
+                Resume::StepOut => depth < hook.depth
+            };
+            step_done.then_some(Stop::Step)
+        };
+
+        if let Some(reason) = reason{
+            hook.resume = hook.debugger.stopped(&self.paused(), reason);
+            hook.depth = depth;
+        }
+
+        let go_on = hook.resume != Resume::Terminate;
+        hook.failed |= !go_on;
+        self.debug = Some(hook);
+        go_on
+    }
+
+    /// Gives the runtime error `msg` to the debugger, where it happened
+    pub(crate) fn debug_error(&mut self, msg:&str){
+        let Some(mut hook) = self.debug.take() else {
+            return;
+        };
+
+        if !hook.failed{
+            hook.failed = true;
+            hook.debugger.stopped(&self.paused(), Stop::Error(msg.to_string()));
+        }
+        self.debug = Some(hook);
+    }
+
+    fn paused(&self) -> Paused<'_>{
+        let frames = self.memory.iter().map(|frame| {
+            let function = self.runtime.scripts.iter()
+                .filter(|e| e.path == frame.script)
+                .flat_map(|e| e.functions.iter())
+                .find(|e| e.label == frame.label);
+
+            let locals = function.map(|f| f.locals.iter()
+                .filter_map(|(name, reg, t)| Some((name.clone(), self.decode(*frame.registers.get(reg)?, *t))))
+                .collect()
+            ).unwrap_or_default();
+
+            Frame{
+                function: function.map(|e| e.name.clone()).unwrap_or_else(|| frame.label.clone()),
+                script: frame.script.clone(),
+                line: frame.line,
+                locals
+            }
+        }).collect();
+
+        Paused{ frames, canvases: &self.canvas }
+    }
+}
@@ -0,0 +1,58 @@
+use std::{fs, path::PathBuf, process::{Command, Output}};
+
+const SCRIPT:&str = "def main(w:int, h:int, col:color){
+    create_canvas(w, h);
+    fill(col);
+    save_canvas();
+}
+";
+
+/// Directory of its own holding `main.pprs`, where the canvases are saved
+fn script_dir(name:&str) -> PathBuf{
+    let dir = std::env::temp_dir().join(format!("papyrus_arguments_{name}_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("main.pprs"), SCRIPT).unwrap();
+    dir
+}
+
+fn papyrus(dir:&PathBuf, args:&[&str]) -> Output{
+    Command::new(env!("CARGO_BIN_EXE_papyrus")).args(args).current_dir(dir).output().unwrap()
+}
+
+#[test]
+fn main_takes_its_arguments_in_order_or_by_name(){
+    let dir = script_dir("valid");
+
+    let output = papyrus(&dir, &["run", "main.pprs", "--", "3", "2", "#ff0000ff"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let image = image::open(dir.join("canvas0.png")).unwrap().to_rgba8();
+    assert_eq!(image.dimensions(), (3, 2));
+    assert_eq!(image.get_pixel(2, 1).0, [255, 0, 0, 255]);
+
+    let output = papyrus(&dir, &["run", "main.pprs", "--arg", "col=#00ff00ff", "--arg", "h=4", "--", "1"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let image = image::open(dir.join("canvas0.png")).unwrap().to_rgba8();
+    assert_eq!(image.dimensions(), (1, 4));
+    assert_eq!(image.get_pixel(0, 3).0, [0, 255, 0, 255]);
+}
+
+#[test]
+fn invalid_arguments_and_runtime_errors_fail(){
+    let dir = script_dir("invalid");
+
+    for (args, error) in [
+        (&["--", "3", "two", "#ff0000ff"][..], "'two' is not a valid int"),
+        (&["--", "3", "2"], "Missing the argument 'col'"),
+        (&["--", "3", "2", "#ff0000ff", "4"], "takes 3 arguments but 4 were given"),
+        (&["--arg", "size=3"], "no parameter named 'size'"),
+        (&["--", "-3", "2", "#ff0000ff"], "Runtime error")
+    ]{
+        let output = papyrus(&dir, &[&["run", "main.pprs"][..], args].concat());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+
+        assert!(!output.status.success(), "{args:?} succeeded");
+        assert!(stderr.contains(error), "{args:?}: {stderr}");
+    }
+}
@@ -0,0 +1,79 @@
+use papyrus::{Canvas, Engine, MemorySources, RuntimeError};
+
+fn engine(script:&str) -> Engine{
+    Engine::with_sources(MemorySources::new().with("main.pprs", script))
+}
+
+fn run(script:&str) -> Result<Vec<Canvas>, RuntimeError>{
+    engine(script).compile("main.pprs").unwrap().run("main", &[])
+}
+
+#[test]
+fn overloads_are_picked_by_their_parameters(){
+    let saved = run("
+def main(){
+    create_canvas(3, 2);
+    put(0, 0, rgb(abs(-3), int(abs(-2.5) * 10.0), max(4, 9)));
+    put(1, 0, rgb(clamp(300, 0, 255), int(clamp(2.0, 0.0, 1.0) * 100.0), min(7, 5)));
+
+    fill_rect(2, 0, 1, 1, #ff0000ff);
+    put(2, 0, #00000000, false);
+    save_canvas();
+
+    create_canvas(4, 4);
+    fill(#ffffffff);
+    resize(2, 1);
+    resize(1, 3, \"nearest\");
+    save_canvas();
+}
+").unwrap();
+
+    let canvas = &saved[0];
+    assert_eq!(canvas.get(0, 0), 0x031909ff);
+    assert_eq!(canvas.get(1, 0), 0xff6405ff);
+    assert_eq!(canvas.get(2, 0), 0);
+
+    assert_eq!((saved[1].width, saved[1].height), (1, 3));
+    assert_eq!(saved[1].get(0, 2), 0xffffffff);
+}
+
+#[test]
+fn builtins_give_back_their_values(){
+    let saved = run("
+def main(){
+    first:int = create_canvas(5, 3);
+    second:int = create_canvas(2, 2);
+
+    select(first);
+    put(0, 0, rgba(width(), height(), red(#0a0b0cff), alpha(#00000080)));
+    put(1, 0, sample(0, 0));
+    put_to(second, 1, 1, sample_from(first, 0, 0));
+
+    save_canvas();
+    save_canvas();
+}
+").unwrap();
+
+    let (first, second) = (&saved[0], &saved[1]);
+    assert_eq!((first.width, first.height), (5, 3));
+    assert_eq!(first.get(0, 0), 0x05030a80);
+    assert_eq!(first.get(1, 0), 0x05030a80);
+    assert_eq!(second.get(1, 1), 0x05030a80);
+}
+
+#[test]
+fn calls_not_matching_a_builtin_are_rejected(){
+    for call in ["put(1, 2);", "abs(true);", "x:int = sqrt(2.0);", "resize(1, 1, 2);", "fill(1);"]{
+        let script = format!("def main(){{\n    create_canvas(1, 1);\n    {call}\n}}\n");
+        assert!(engine(&script).compile("main.pprs").is_err(), "{call} compiled");
+    }
+}
+
+#[test]
+fn builtin_errors_stop_the_script(){
+    let no_canvas = run("def main(){\n    fill(#ffffffff);\n}\n").unwrap_err();
+    assert!(no_canvas.message.contains("fill has no canvas to work on"), "{no_canvas}");
+
+    let filter = run("def main(){\n    create_canvas(2, 2);\n    resize(1, 1, \"blocky\");\n}\n").unwrap_err();
+    assert!(filter.message.contains("Unknown filter 'blocky'"), "{filter}");
+}
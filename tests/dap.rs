@@ -0,0 +1,156 @@
+use std::{fs, io::Cursor, path::PathBuf};
+
+use serde_json::{json, Value as Json};
+
+const SCRIPT:&str = "def main(n:int){
+    create_canvas(n, n);
+    i:int = 0;
+
+    while (i < n){
+        put(i, i, #ff0000ff);
+        i = i + 1;
+    }
+}
+";
+
+/// Writes `script` in a directory of its own, giving its path
+fn write_script(name:&str, script:&str) -> PathBuf{
+    let dir = std::env::temp_dir().join(format!("papyrus_dap_{name}_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let path = dir.join("main.pprs");
+    fs::write(&path, script).unwrap();
+    path
+}
+
+/// Frames the bodies the way a client sends them
+fn frame(bodies:&[String]) -> Vec<u8>{
+    bodies.iter().flat_map(|body| format!("Content-Length: {}\r\n\r\n{body}", body.len()).into_bytes()).collect()
+}
+
+fn requests(requests:&[Json]) -> Vec<String>{
+    requests.iter().enumerate().map(|(i, request)| {
+        let mut request = request.clone();
+        request["seq"] = (i + 1).into();
+        request["type"] = "request".into();
+        request.to_string()
+    }).collect()
+}
+
+/// Runs the server on `input`, giving the messages it sent
+fn serve(input:Vec<u8>) -> Vec<Json>{
+    let mut output = vec![];
+    papyrus::dap::serve(Cursor::new(input), &mut output).unwrap();
+
+    let mut output = output.as_slice();
+    let mut messages = vec![];
+
+    while !output.is_empty(){
+        let header_end = output.windows(4).position(|e| e == b"\r\n\r\n").unwrap();
+        let header = std::str::from_utf8(&output[..header_end]).unwrap();
+        let length:usize = header.strip_prefix("Content-Length: ").unwrap().parse().unwrap();
+
+        let body = &output[header_end + 4..header_end + 4 + length];
+        messages.push(serde_json::from_slice(body).unwrap());
+        output = &output[header_end + 4 + length..];
+    }
+
+    messages
+}
+
+fn response<'a>(messages:&'a [Json], command:&str) -> Vec<&'a Json>{
+    messages.iter().filter(|e| e["type"] == "response" && e["command"] == command).collect()
+}
+
+fn events<'a>(messages:&'a [Json], event:&str) -> Vec<&'a Json>{
+    messages.iter().filter(|e| e["type"] == "event" && e["event"] == event).collect()
+}
+
+#[test]
+fn stops_at_breakpoint_and_runs_to_the_end(){
+    let path = write_script("breakpoint", SCRIPT);
+    let program = path.to_string_lossy();
+
+    let messages = serve(frame(&requests(&[
+        json!({ "command": "initialize", "arguments": { "adapterID": "papyrus" } }),
+        json!({ "command": "launch", "arguments": { "program": program, "args": [3] } }),
+        json!({ "command": "setBreakpoints", "arguments": {
+            "source": { "path": program },
+            "breakpoints": [{ "line": 1 }, { "line": 40 }]
+        } }),
+        json!({ "command": "configurationDone" }),
+        json!({ "command": "stackTrace", "arguments": { "threadId": 1 } }),
+        json!({ "command": "continue", "arguments": { "threadId": 1 } }),
+        json!({ "command": "disconnect" })
+    ])));
+
+    let initialize = response(&messages, "initialize");
+    assert_eq!(initialize.len(), 1);
+    assert_eq!(initialize[0]["success"], true);
+    assert_eq!(initialize[0]["body"]["supportsConfigurationDoneRequest"], true);
+
+    assert_eq!(response(&messages, "launch")[0]["success"], true);
+    assert_eq!(events(&messages, "initialized").len(), 1);
+
+    let breakpoints = &response(&messages, "setBreakpoints")[0]["body"]["breakpoints"];
+    assert_eq!(breakpoints[0], json!({ "verified": true, "line": 2 }));
+    assert_eq!(breakpoints[1]["verified"], false);
+
+    assert_eq!(response(&messages, "configurationDone")[0]["success"], true);
+
+    let stopped = events(&messages, "stopped");
+    assert_eq!(stopped.len(), 1);
+    assert_eq!(stopped[0]["body"]["reason"], "breakpoint");
+
+    let frames = &response(&messages, "stackTrace")[0]["body"]["stackFrames"];
+    assert_eq!(frames.as_array().unwrap().len(), 1);
+    assert_eq!(frames[0]["name"], "main");
+    assert_eq!(frames[0]["line"], 2);
+    assert_eq!(frames[0]["source"]["path"], program.as_ref());
+
+    assert_eq!(response(&messages, "continue")[0]["success"], true);
+
+    let exited = events(&messages, "exited");
+    assert_eq!(exited.len(), 1);
+    assert_eq!(exited[0]["body"]["exitCode"], 0);
+    assert_eq!(events(&messages, "terminated").len(), 1);
+
+    assert_eq!(response(&messages, "disconnect")[0]["success"], true);
+
+    let seqs = messages.iter().map(|e| e["seq"].as_i64().unwrap()).collect::<Vec<i64>>();
+    assert_eq!(seqs, (1..=messages.len() as i64).collect::<Vec<i64>>());
+}
+
+#[test]
+fn requests_needing_a_stopped_script_fail(){
+    let messages = serve(frame(&requests(&[
+        json!({ "command": "stackTrace", "arguments": { "threadId": 1 } }),
+        json!({ "command": "continue", "arguments": { "threadId": 1 } }),
+        json!({ "command": "launch", "arguments": { "program": "does_not_exist.pprs" } }),
+        json!({ "command": "disconnect" })
+    ])));
+
+    assert_eq!(response(&messages, "stackTrace")[0]["success"], false);
+    assert_eq!(response(&messages, "continue")[0]["success"], false);
+    assert_eq!(response(&messages, "launch")[0]["success"], false);
+    assert!(events(&messages, "initialized").is_empty());
+    assert_eq!(response(&messages, "disconnect")[0]["success"], true);
+}
+
+#[test]
+fn malformed_messages_get_an_error_and_serving_goes_on(){
+    let mut bodies = vec!["{\"seq\":1,\"type\":\"request\",\"command\":".to_string(), "[1, 2]".to_string()];
+    bodies.extend(requests(&[json!({ "command": "initialize" }), json!({ "command": "disconnect" })]));
+
+    let messages = serve(frame(&bodies));
+
+    assert_eq!(messages.len(), 4);
+    for message in &messages[..2]{
+        assert_eq!(message["type"], "response");
+        assert_eq!(message["success"], false);
+        assert!(message["message"].as_str().unwrap().starts_with("Invalid message"));
+    }
+
+    assert_eq!(response(&messages, "initialize")[0]["success"], true);
+    assert_eq!(response(&messages, "disconnect")[0]["success"], true);
+}
@@ -0,0 +1,161 @@
+use std::{collections::VecDeque, path::Path};
+
+use papyrus::{Debugger, Engine, FuncSign, MemorySources, Paused, Program, Resume, Stop, Type, Value};
+
+const MAIN:&str = "import \"lib\";
+
+def add(a:int, b:int): int {
+    s:int = a + b;
+    return s;
+}
+
+def main(){
+    create_canvas(2, 3);
+    x:int = add(1, 2);
+    y:int = lib.twice(x);
+    put(0, 0, #ff0000ff);
+    save_canvas();
+}
+";
+
+const LIB:&str = "pub def twice(v:int): int {
+    return v * 2;
+}
+";
+
+/// Where the script stopped: why, in which function, script and line, and the locals of that function
+#[derive(Debug, PartialEq)]
+struct Stopped{
+    reason: Stop,
+    function: String,
+    script: String,
+    line: usize,
+    depth: usize,
+    locals: Vec<(String, Value)>,
+    canvases: Vec<(u32, u32)>
+}
+
+/// Stops at the `breakpoints`, given as script name and line, and goes on as told by `resumes`
+struct Scripted{
+    breakpoints: Vec<(&'static str, usize)>,
+    resumes: VecDeque<Resume>,
+    stops: Vec<Stopped>
+}
+
+impl Scripted{
+    fn new(breakpoints:&[(&'static str, usize)], resumes:&[Resume]) -> Self{
+        Scripted{ breakpoints: breakpoints.to_vec(), resumes: resumes.iter().copied().collect(), stops: vec![] }
+    }
+}
+
+fn script_name(path:&Path) -> String{
+    path.file_name().unwrap().to_string_lossy().to_string()
+}
+
+impl Debugger for Scripted{
+    fn breakpoint(&self, path:&Path, line:usize) -> bool{
+        self.breakpoints.contains(&(script_name(path).as_str(), line))
+    }
+
+    fn stopped(&mut self, paused:&Paused, reason:Stop) -> Resume{
+        let frame = &paused.frames[0];
+        self.stops.push(Stopped{
+            reason,
+            function: frame.function.clone(),
+            script: script_name(&frame.script),
+            line: frame.line,
+            depth: paused.frames.len(),
+            locals: frame.locals.clone(),
+            canvases: paused.canvases.iter().map(|e| (e.width, e.height)).collect()
+        });
+
+        self.resumes.pop_front().unwrap_or(Resume::Continue)
+    }
+}
+
+/// Compiles `main.pprs` from `sources`, its functions being able to call `fail()` which stops them with a runtime error
+fn compile(sources:MemorySources) -> Program{
+    let mut engine = Engine::with_sources(sources);
+    engine.register(FuncSign{ name: "fail".to_string(), params: vec![] }, Type::Int, |_| Err("failed on purpose".to_string())).unwrap();
+    engine.compile("main.pprs").unwrap()
+}
+
+fn program() -> Program{
+    compile(MemorySources::new().with("main.pprs", MAIN).with("lib.pprs", LIB))
+}
+
+/// Where the script stopped, as (reason, function, script, line)
+fn positions(debugger:&Scripted) -> Vec<(Stop, &str, &str, usize)>{
+    debugger.stops.iter().map(|e| (e.reason.clone(), e.function.as_str(), e.script.as_str(), e.line)).collect()
+}
+
+#[test]
+fn stops_at_breakpoints_with_the_locals_and_canvases(){
+    let program = program();
+    let mut debugger = Scripted::new(&[("main.pprs", 12), ("lib.pprs", 2)], &[]);
+
+    let saved = program.debug("main", &[], &mut debugger, false).unwrap();
+    assert_eq!(saved.len(), 1);
+
+    assert_eq!(positions(&debugger), vec![
+        (Stop::Breakpoint, "twice", "lib.pprs", 2),
+        (Stop::Breakpoint, "main", "main.pprs", 12)
+    ]);
+
+    assert_eq!(debugger.stops[0].depth, 2);
+    assert_eq!(debugger.stops[0].locals, vec![("v".to_string(), Value::Int(3))]);
+
+    let main = &debugger.stops[1];
+    assert_eq!(main.depth, 1);
+    assert!(main.locals.contains(&("x".to_string(), Value::Int(3))));
+    assert!(main.locals.contains(&("y".to_string(), Value::Int(6))));
+    assert_eq!(main.canvases, vec![(2, 3)]);
+}
+
+#[test]
+fn steps_over_into_and_out_of_functions(){
+    let program = program();
+    let mut debugger = Scripted::new(&[], &[
+        Resume::StepOver, Resume::StepInto, Resume::StepOver, Resume::StepOut,
+        Resume::StepInto, Resume::StepOver, Resume::Continue
+    ]);
+
+    program.debug("main", &[], &mut debugger, true).unwrap();
+
+    assert_eq!(positions(&debugger), vec![
+        (Stop::Entry, "main", "main.pprs", 9),
+        (Stop::Step, "main", "main.pprs", 10),
+        (Stop::Step, "add", "main.pprs", 4),
+        (Stop::Step, "add", "main.pprs", 5),
+        (Stop::Step, "main", "main.pprs", 11),
+        (Stop::Step, "twice", "lib.pprs", 2),
+        (Stop::Step, "main", "main.pprs", 12)
+    ]);
+
+    assert_eq!(debugger.stops[2].locals, vec![("a".to_string(), Value::Int(1)), ("b".to_string(), Value::Int(2))]);
+    assert!(debugger.stops[3].locals.contains(&("s".to_string(), Value::Int(3))));
+}
+
+#[test]
+fn terminate_ends_the_script(){
+    let program = program();
+    let mut debugger = Scripted::new(&[("main.pprs", 11)], &[Resume::Terminate]);
+
+    assert!(program.debug("main", &[], &mut debugger, false).is_err());
+    assert_eq!(positions(&debugger), vec![(Stop::Breakpoint, "main", "main.pprs", 11)]);
+}
+
+#[test]
+fn stops_where_a_runtime_error_happens(){
+    let program = compile(MemorySources::new().with("main.pprs", "def main(){\n    x:int = 4;\n    x = fail();\n}\n"));
+    let mut debugger = Scripted::new(&[], &[]);
+
+    let error = program.debug("main", &[], &mut debugger, false).unwrap_err();
+    assert!(error.message.contains("failed on purpose"), "{error}");
+
+    assert_eq!(debugger.stops.len(), 1);
+    let stop = &debugger.stops[0];
+    assert!(matches!(&stop.reason, Stop::Error(e) if e.contains("failed on purpose")), "{:?}", stop.reason);
+    assert_eq!((stop.function.as_str(), stop.line), ("main", 3));
+    assert_eq!(stop.locals, vec![("x".to_string(), Value::Int(4))]);
+}
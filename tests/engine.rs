@@ -0,0 +1,66 @@
+use std::{fs, path::PathBuf};
+
+use papyrus::{Engine, Value};
+
+/// Writes the `scripts`, given as name and content, in a directory of its own and gives the path of the first one
+fn write_scripts(dir:&str, scripts:&[(&str, &str)]) -> PathBuf{
+    let dir = std::env::temp_dir().join(format!("papyrus_engine_{dir}_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    for (name, content) in scripts{
+        fs::write(dir.join(name), content).unwrap();
+    }
+    dir.join(scripts[0].0)
+}
+
+#[test]
+fn runs_a_compiled_script_with_arguments(){
+    let path = write_scripts("run", &[
+        ("main.pprs", "import \"shapes\";\n\ndef main(w:int, h:int, col:color){\n    create_canvas(w, h);\n    fill(col);\n    shapes.dot(1, 0);\n    save_canvas();\n}\n"),
+        ("shapes.pprs", "pub def dot(x:int, y:int){\n    put(x, y, #ffffffff);\n}\n")
+    ]);
+
+    let program = Engine::new().compile(&path).unwrap();
+    assert_eq!(program.path(), path);
+    assert_eq!(program.scripts().len(), 2);
+
+    let saved = program.run("main", &[Value::Int(3), Value::Int(2), Value::Color(0x112233ff)]).unwrap();
+    assert_eq!(saved.len(), 1);
+    assert_eq!((saved[0].width, saved[0].height), (3, 2));
+    assert_eq!(saved[0].get(0, 0), 0x112233ff);
+    assert_eq!(saved[0].get(1, 0), 0xffffffff);
+
+    // A program can be run again, from a fresh state
+    let saved = program.run("main", &[Value::Int(1), Value::Int(1), Value::Color(0x000000ff)]).unwrap();
+    assert_eq!((saved[0].width, saved[0].height, saved[0].get(0, 0)), (1, 1, 0x000000ff));
+}
+
+#[test]
+fn compile_errors_are_given_back_with_their_script(){
+    let path = write_scripts("diagnostics", &[("main.pprs", "def main(){\n    x:int = true;\n}\n")]);
+
+    let diagnostics = Engine::new().compile(&path).unwrap_err();
+    assert_eq!(diagnostics.errors.len(), 1);
+    assert!(diagnostics.to_string().contains("main.pprs"), "{diagnostics}");
+    assert_eq!(diagnostics.scripts, vec![path]);
+
+    assert!(Engine::new().compile(std::env::temp_dir().join("papyrus_missing.pprs")).is_err());
+}
+
+#[test]
+fn run_checks_the_entry_and_its_arguments(){
+    let path = write_scripts("arguments", &[("main.pprs", "def main(n:int){\n    create_canvas(n, n);\n    save_canvas();\n}\n")]);
+    let program = Engine::new().compile(&path).unwrap();
+
+    let unknown = program.run("other", &[]).unwrap_err();
+    assert!(unknown.message.contains("No function named other"), "{unknown}");
+
+    let count = program.run("main", &[]).unwrap_err();
+    assert!(count.message.contains("takes 1 arguments"), "{count}");
+
+    let wrong_type = program.run("main", &[Value::Float(2.0)]).unwrap_err();
+    assert!(wrong_type.message.contains("must be of type int"), "{wrong_type}");
+
+    let runtime = program.run("main", &[Value::Int(-1)]);
+    assert!(runtime.is_err());
+}
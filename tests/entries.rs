@@ -0,0 +1,88 @@
+use std::{fs, path::PathBuf, process::{Command, Output}};
+
+use papyrus::{Engine, MemorySources, Value};
+
+const SCRIPT:&str = "pub def twice(a:int){
+    create_canvas(a * 2, 1);
+    save_canvas();
+}
+
+pub def twice(a:float){
+    create_canvas(int(a * 2.0), 2);
+    save_canvas();
+}
+
+def hidden(){
+    create_canvas(1, 1);
+    save_canvas();
+}
+";
+
+/// Directory of its own holding `main.pprs`, where the canvases are saved
+fn script_dir(name:&str) -> PathBuf{
+    let dir = std::env::temp_dir().join(format!("papyrus_entries_{name}_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("main.pprs"), SCRIPT).unwrap();
+    dir
+}
+
+fn papyrus(dir:&PathBuf, args:&[&str]) -> Output{
+    Command::new(env!("CARGO_BIN_EXE_papyrus")).args(args).current_dir(dir).output().unwrap()
+}
+
+fn saved_size(dir:&PathBuf) -> (u32, u32){
+    image::open(dir.join("canvas0.png")).unwrap().to_rgba8().dimensions()
+}
+
+#[test]
+fn overloaded_entries_are_picked_by_their_arguments(){
+    let dir = script_dir("overloads");
+
+    let list = papyrus(&dir, &["list-entries", "main.pprs"]);
+    assert_eq!(String::from_utf8_lossy(&list.stdout), "twice(a:int)\ntwice(a:float)\n");
+
+    let float = papyrus(&dir, &["run", "main.pprs", "--entry", "twice", "--", "1.5"]);
+    assert!(float.stderr.is_empty(), "{}", String::from_utf8_lossy(&float.stderr));
+    assert_eq!(saved_size(&dir), (3, 2));
+
+    let int = papyrus(&dir, &["run", "main.pprs", "--entry", "twice", "--arg", "a=2"]);
+    assert!(int.stderr.is_empty(), "{}", String::from_utf8_lossy(&int.stderr));
+    assert_eq!(saved_size(&dir), (4, 1));
+
+    let neither = papyrus(&dir, &["run", "main.pprs", "--entry", "twice", "--", "abc"]);
+    assert!(!neither.status.success());
+    let error = String::from_utf8_lossy(&neither.stderr);
+    assert!(error.contains("No definition of twice takes these arguments"), "{error}");
+    assert!(error.contains("twice(a:int)") && error.contains("twice(a:float)"), "{error}");
+}
+
+#[test]
+fn unknown_and_private_entries_fail(){
+    let dir = script_dir("unknown");
+
+    for (entry, error) in [("other", "No function named other"), ("hidden", "The function hidden is not public")]{
+        for command in ["run", "debug"]{
+            let output = papyrus(&dir, &[command, "main.pprs", "--entry", entry]);
+            let stderr = String::from_utf8_lossy(&output.stderr);
+
+            assert!(!output.status.success(), "{command} --entry {entry} succeeded");
+            assert!(stderr.contains(error), "{command} --entry {entry}: {stderr}");
+        }
+    }
+
+    assert!(!papyrus(&dir, &["list-entries", "missing.pprs"]).status.success());
+}
+
+#[test]
+fn programs_run_the_overload_matching_the_argument_types(){
+    let program = Engine::with_sources(MemorySources::new().with("main.pprs", SCRIPT)).compile("main.pprs").unwrap();
+
+    let saved = program.run("twice", &[Value::Float(2.5)]).unwrap();
+    assert_eq!((saved[0].width, saved[0].height), (5, 2));
+
+    let saved = program.run("twice", &[Value::Int(3)]).unwrap();
+    assert_eq!((saved[0].width, saved[0].height), (6, 1));
+
+    let error = program.run("twice", &[Value::Bool(true)]).unwrap_err();
+    assert!(error.message.contains("No definition of twice takes arguments of types (bool)"), "{error}");
+}
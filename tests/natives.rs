@@ -0,0 +1,114 @@
+use std::{cell::RefCell, rc::Rc};
+
+use papyrus::{Canvas, Engine, FuncSign, MemorySources, RuntimeError, Type, Value};
+
+fn sign(name:&str, params:&[Type]) -> FuncSign{
+    FuncSign{ name: name.to_string(), params: params.to_vec() }
+}
+
+/// An engine whose scripts can call `lookup_sales(month)`, giving the sales of the first three months and failing for the others
+fn engine(script:&str) -> Engine{
+    let mut engine = Engine::with_sources(MemorySources::new().with("main.pprs", script));
+    engine.register(sign("lookup_sales", &[Type::Int]), Type::Int, |args| match args{
+        [Value::Int(month @ 1..=3)] => Ok(Value::Int(month * 100)),
+        [Value::Int(month)] => Err(format!("No sales for month {month}")),
+        _ => unreachable!()
+    }).unwrap();
+
+    engine
+}
+
+fn run(script:&str) -> Result<Vec<Canvas>, RuntimeError>{
+    engine(script).compile("main.pprs").unwrap().run("main", &[])
+}
+
+#[test]
+fn scripts_call_native_functions(){
+    let saved = run("
+def main(){
+    create_canvas(4, 1);
+    month:int = 1;
+
+    while (month <= 3){
+        if (lookup_sales(month) >= 200){
+            put(month, 0, #00ff00ff);
+        }
+        month = month + 1;
+    }
+    save_canvas();
+}
+").unwrap();
+
+    let canvas = &saved[0];
+    assert_eq!(canvas.get(1, 0), 0);
+    assert_eq!(canvas.get(2, 0), 0x00ff00ff);
+    assert_eq!(canvas.get(3, 0), 0x00ff00ff);
+}
+
+#[test]
+fn native_errors_stop_the_script(){
+    let error = run("
+def main(){
+    create_canvas(1, 1);
+    sales:int = lookup_sales(7);
+    save_canvas();
+}
+").unwrap_err();
+
+    assert!(error.message.contains("No sales for month 7"), "{error}");
+}
+
+#[test]
+fn calls_are_checked_against_the_signature(){
+    let script = "def main(){\n    sales:int = lookup_sales(1.5);\n}\n";
+    let diagnostics = engine(script).compile("main.pprs").unwrap_err();
+    assert!(diagnostics.to_string().contains("lookup_sales"), "{diagnostics}");
+
+    let script = "def main(){\n    sales:color = lookup_sales(1);\n}\n";
+    assert!(engine(script).compile("main.pprs").is_err());
+
+    // Without the native function registered, the call is unknown
+    let sources = MemorySources::new().with("main.pprs", "def main(){\n    sales:int = lookup_sales(1);\n}\n");
+    assert!(Engine::with_sources(sources).compile("main.pprs").is_err());
+}
+
+#[test]
+fn natives_take_every_type_and_can_be_overloaded(){
+    let calls = Rc::new(RefCell::new(vec![]));
+    let mut engine = Engine::with_sources(MemorySources::new().with("main.pprs", "
+def main(){
+    log(\"start\", 2, 0.5, true, #ff0000ff);
+    log(\"end\");
+}
+"));
+
+    let record = calls.clone();
+    engine.register(sign("log", &[Type::String, Type::Int, Type::Float, Type::Bool, Type::Color]), Type::Void, move |args| {
+        record.borrow_mut().push(args.to_vec());
+        Ok(Value::Void)
+    }).unwrap();
+
+    let record = calls.clone();
+    engine.register(sign("log", &[Type::String]), Type::Void, move |args| {
+        record.borrow_mut().push(args.to_vec());
+        Ok(Value::Void)
+    }).unwrap();
+
+    engine.compile("main.pprs").unwrap().run("main", &[]).unwrap();
+    assert_eq!(*calls.borrow(), vec![
+        vec![Value::String("start".to_string()), Value::Int(2), Value::Float(0.5), Value::Bool(true), Value::Color(0xff0000ff)],
+        vec![Value::String("end".to_string())]
+    ]);
+}
+
+#[test]
+fn registering_an_existing_signature_fails(){
+    let mut engine = engine("");
+    let ok = |_:&[Value]| Ok(Value::Void);
+
+    assert!(engine.register(sign("lookup_sales", &[Type::Int]), Type::Int, ok).is_err());
+    assert!(engine.register(sign("put", &[Type::Int, Type::Int, Type::Color]), Type::Void, ok).is_err());
+    assert!(engine.register(sign("nothing", &[Type::Void]), Type::Void, ok).is_err());
+
+    assert!(engine.register(sign("lookup_sales", &[Type::Int, Type::Int]), Type::Int, ok).is_ok());
+}
@@ -0,0 +1,85 @@
+use std::{fs::{self, File}, path::PathBuf};
+
+use image::{Rgba, RgbaImage};
+use papyrus::palette::{Dither, Indexed, PaletteSource};
+
+/// Path of a file in a directory of its own
+fn output(name:&str) -> PathBuf{
+    let dir = std::env::temp_dir().join(format!("papyrus_palette_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir.join(name)
+}
+
+/// Colors of the palette of an indexed png, with their alpha, and the index of each pixel
+fn read_indexed(path:&PathBuf) -> (Vec<[u8; 4]>, Vec<u8>){
+    let mut reader = png::Decoder::new(File::open(path).unwrap()).read_info().unwrap();
+    let mut indices = vec![0; reader.output_buffer_size()];
+    reader.next_frame(&mut indices).unwrap();
+
+    let info = reader.info();
+    assert_eq!(info.color_type, png::ColorType::Indexed);
+
+    let rgb = info.palette.as_ref().unwrap();
+    let alpha = info.trns.as_deref().unwrap_or_default();
+    let colors = rgb.chunks_exact(3).enumerate()
+        .map(|(i, e)| [e[0], e[1], e[2], alpha.get(i).copied().unwrap_or(255)])
+        .collect();
+
+    (colors, indices)
+}
+
+/// Exports `image` with an automatic palette of `size` colors
+fn export(name:&str, image:&RgbaImage, size:usize) -> (Vec<[u8; 4]>, Vec<u8>){
+    let palette = PaletteSource::parse(&size.to_string()).unwrap().palette_for(image);
+    let path = output(name);
+    Indexed::new(image, palette, Dither::None).save_png(path.to_str().unwrap()).unwrap();
+
+    read_indexed(&path)
+}
+
+fn assert_close(color:[u8; 4], expected:[u8; 4]){
+    let close = color.iter().zip(expected).all(|(a, b)| a.abs_diff(b) <= 8);
+    assert!(close, "{color:?} is not close to {expected:?}");
+}
+
+#[test]
+fn automatic_palette_is_opaque(){
+    let image = RgbaImage::from_pixel(1, 1, Rgba([255, 0, 0, 128]));
+    let (colors, indices) = export("single.png", &image, 256);
+
+    assert!(colors.iter().all(|e| e[3] == 255), "{colors:?}");
+    assert_close(colors[indices[0] as usize], [255, 0, 0, 255]);
+}
+
+#[test]
+fn semi_transparent_pixels_take_the_nearest_opaque_color(){
+    let mut image = RgbaImage::new(64, 64);
+    for (x, y, pixel) in image.enumerate_pixels_mut(){
+        *pixel = match (x / 32, y / 32){
+            (0, 0) => Rgba([255, 0, 0, 255]),
+            (1, 0) => Rgba([0, 0, 255, 255]),
+            (0, 1) => Rgba([0, 255, 0, 128]),
+            _ => Rgba([0, 0, 0, 0])
+        };
+    }
+
+    let (colors, indices) = export("quadrants.png", &image, 4);
+    let color_at = |x:usize, y:usize| colors[indices[y * 64 + x] as usize];
+
+    assert_close(color_at(0, 0), [255, 0, 0, 255]);
+    assert_close(color_at(40, 0), [0, 0, 255, 255]);
+    assert_close(color_at(0, 40), [0, 255, 0, 255]);
+    assert_eq!(color_at(40, 40)[3], 0);
+
+    // Only the color added for the transparent pixels isn't opaque
+    assert_eq!(colors.iter().filter(|e| e[3] != 255).count(), 1);
+}
+
+#[test]
+fn quantized_palette_is_opaque(){
+    let image = RgbaImage::from_fn(64, 64, |x, y| Rgba([(x * 4) as u8, (y * 4) as u8, 128, (x + y) as u8 * 2 + 1]));
+    let (colors, _) = export("gradient.png", &image, 8);
+
+    assert_eq!(colors.len(), 8);
+    assert!(colors.iter().all(|e| e[3] == 255), "{colors:?}");
+}
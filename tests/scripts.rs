@@ -0,0 +1,178 @@
+use papyrus::{Canvas, Engine, MemorySources, RuntimeError};
+
+/// Runs the `main` function of `script`
+fn run(script:&str) -> Result<Vec<Canvas>, RuntimeError>{
+    let engine = Engine::with_sources(MemorySources::new().with("main.pprs", script));
+    engine.compile("main.pprs").unwrap().run("main", &[])
+}
+
+fn run_err(script:&str) -> String{
+    match run(script){
+        Ok(saved) => panic!("Expected a runtime error, {} canvas(es) were saved", saved.len()),
+        Err(e) => e.message
+    }
+}
+
+#[test]
+fn select_fails_in_functions_called_from_a_subcanvas(){
+    let error = run_err("
+def pick(id:int){
+    select(id);
+}
+
+def main(){
+    base:int = create_canvas(4, 4);
+    subcanvas(0, 0, 2, 2){
+        pick(base);
+    }
+    save_canvas();
+}
+");
+    assert!(error.contains("select"), "{error}");
+}
+
+#[test]
+fn subcanvas_fails_when_the_stack_changed_in_the_block(){
+    let leaves_a_canvas = run_err("
+def scratch(){
+    create_canvas(8, 8);
+}
+
+def main(){
+    create_canvas(4, 4);
+    subcanvas(0, 0, 2, 2){
+        scratch();
+    }
+    save_canvas();
+}
+");
+    assert!(leaves_a_canvas.contains("canvas stack changed"), "{leaves_a_canvas}");
+
+    let saves_the_subcanvas = run_err("
+def done(){
+    save_canvas();
+}
+
+def main(){
+    create_canvas(4, 4);
+    subcanvas(0, 0, 2, 2){
+        done();
+    }
+    save_canvas();
+}
+");
+    assert!(saves_the_subcanvas.contains("canvas stack changed"), "{saves_the_subcanvas}");
+}
+
+#[test]
+fn subcanvas_merges_when_the_stack_is_left_as_it_was(){
+    let saved = run("
+def scratch(){
+    create_canvas(8, 8);
+    fill(#0000ffff);
+    save_canvas();
+}
+
+def main(){
+    create_canvas(4, 4);
+    fill(#ff0000ff);
+
+    subcanvas(1, 1, 2, 2){
+        scratch();
+        fill(#00ff00ff);
+
+        subcanvas_copy(0, 0, 1, 1){
+            fill(#ffffffff);
+        }
+    }
+
+    subcanvas(0, 3, 2, 2){
+        fill(#000000ff);
+        resize(1, 1);
+    }
+    save_canvas();
+}
+").unwrap();
+
+    assert_eq!(saved.len(), 2);
+    assert_eq!((saved[0].width, saved[0].height), (8, 8));
+
+    let canvas = &saved[1];
+    assert_eq!((canvas.width, canvas.height), (4, 4));
+    assert_eq!(canvas.get(0, 0), 0xff0000ff);
+    assert_eq!(canvas.get(1, 1), 0xffffffff);
+    assert_eq!(canvas.get(2, 2), 0x00ff00ff);
+    assert_eq!(canvas.get(0, 3), 0x000000ff);
+    assert_eq!(canvas.get(1, 3), 0xff0000ff);
+}
+
+#[test]
+fn every_primitive_uses_the_blend_mode(){
+    let saved = run("
+def main(){
+    create_canvas(4, 6);
+    fill(#ff00ffff);
+    blend_mode(\"multiply\");
+
+    line(0, 0, 3, 0, #0000ff80);
+    set_stroke(2);
+    line(0, 2, 3, 2, #0000ff80);
+    set_stroke(1);
+
+    put(0, 4, #0000ff80);
+    fill_rect(1, 4, 1, 1, #0000ff80);
+    put(2, 4, #0000ff80, false);
+
+    blend_mode(\"replace\");
+    fill_rect(0, 5, 2, 1, #00000000);
+    save_canvas();
+}
+").unwrap();
+
+    let canvas = &saved[0];
+    let multiplied = canvas.get(0, 0);
+    assert_ne!(multiplied, 0x0000ff80);
+    assert_eq!(canvas.get(0, 2), multiplied);
+    assert_eq!(canvas.get(0, 4), multiplied);
+    assert_eq!(canvas.get(1, 4), multiplied);
+
+    assert_eq!(canvas.get(2, 4), 0x0000ff80);
+    assert_eq!(canvas.get(0, 5), 0);
+    assert_eq!(canvas.get(2, 5), 0xff00ffff);
+}
+
+#[test]
+fn fonts_are_read_from_the_sources(){
+    const SCRIPT:&str = "def main(){\n    load_font(\"fonts/font.ttf\");\n}\n";
+    let run_with = |sources:MemorySources, path:&str| {
+        Engine::with_sources(sources).compile(path).unwrap().run("main", &[]).unwrap_err().message
+    };
+
+    let missing = run_with(MemorySources::new().with("main.pprs", SCRIPT), "main.pprs");
+    assert!(missing.contains("fonts/font.ttf doesn't exist"), "{missing}");
+
+    let sources = MemorySources::new().with("lib/main.pprs", SCRIPT).with("lib/fonts/font.ttf", vec![0u8, 1, 2, 3]);
+    let invalid = run_with(sources, "lib/main.pprs");
+    assert!(invalid.starts_with("Unable to load font lib/fonts/font.ttf") && !invalid.contains("doesn't exist"), "{invalid}");
+
+    let no_directory = run_with(MemorySources::new().with("/", SCRIPT), "/");
+    assert!(no_directory.contains("no directory"), "{no_directory}");
+}
+
+#[test]
+fn integer_overflow_stops_the_script(){
+    for (expression, error) in [
+        ("big + 1", "Integer overflow in 2147483647 + 1"),
+        ("0 - big - 2", "Integer overflow in -2147483647 - 2"),
+        ("big * 2", "Integer overflow in 2147483647 * 2"),
+        ("(0 - big - 1) / (0 - 1)", "Integer overflow in -2147483648 / -1"),
+        ("2 ^ 31", "Integer overflow in 2 ^ 31"),
+        ("1 / 0", "Division by zero")
+    ]{
+        let message = run_err(&format!("def main(){{\n    big:int = 2147483647;\n    x:int = {expression};\n}}\n"));
+        assert!(message.contains(error), "{expression}: {message}");
+    }
+
+    let saved = run("def main(){\n    big:int = 2147483647;\n    create_canvas(1, 1);\n    put(0, 0, rgb((0 - big - 1) % (0 - 1), 2 ^ 30 / 2 ^ 23, big - big));\n    save_canvas();\n}\n").unwrap();
+    assert_eq!(saved[0].get(0, 0), 0x008000ff);
+}
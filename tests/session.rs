@@ -0,0 +1,116 @@
+use std::{fs, io::Write, process::{Command, Stdio}};
+
+use papyrus::{Engine, EvalError, FuncSign, Type, Value};
+
+/// An engine whose scripts can call `fail(msg)`, stopping them with `msg` as runtime error
+fn failing_engine() -> Engine{
+    let mut engine = Engine::new();
+    engine.register(FuncSign{ name: "fail".to_string(), params: vec![Type::String] }, Type::Int, |args| match &args[0]{
+        Value::String(msg) => Err(msg.clone()),
+        _ => unreachable!()
+    }).unwrap();
+
+    engine
+}
+
+#[test]
+fn keeps_variables_and_functions_between_inputs(){
+    let mut session = Engine::new().session();
+
+    assert_eq!(session.eval("x:int = 20;").unwrap(), Value::Void);
+    assert_eq!(session.eval("def twice(a:int): int { return a * 2; }").unwrap(), Value::Void);
+    assert_eq!(session.eval("x = x + 1").unwrap(), Value::Void);
+
+    assert_eq!(session.eval("twice(x)").unwrap(), Value::Int(42));
+    assert_eq!(session.eval("x > 20").unwrap(), Value::Bool(true));
+    assert_eq!(session.eval("\"text\"").unwrap(), Value::String("text".to_string()));
+}
+
+#[test]
+fn keeps_canvases_between_inputs(){
+    let mut session = Engine::new().session();
+
+    session.eval("create_canvas(4, 3); fill(#ff0000ff);").unwrap();
+    session.eval("put(1, 2, #00ff00ff);").unwrap();
+
+    let canvas = &session.canvases()[0];
+    assert_eq!((canvas.width, canvas.height), (4, 3));
+    assert_eq!(canvas.get(0, 0), 0xff0000ff);
+    assert_eq!(canvas.get(1, 2), 0x00ff00ff);
+
+    session.eval("save_canvas();").unwrap();
+    assert!(session.canvases().is_empty());
+    assert_eq!(session.saved().len(), 1);
+}
+
+#[test]
+fn rejects_invalid_inputs_without_changing_the_session(){
+    let mut session = Engine::new().session();
+    session.eval("x:int = 1;").unwrap();
+
+    assert!(matches!(session.eval("y:int = true;"), Err(EvalError::Compile(_))));
+    assert!(matches!(session.eval("x:int = 2;"), Err(EvalError::Compile(_))));
+    assert!(matches!(session.eval("unknown(1)"), Err(EvalError::Compile(_))));
+
+    assert_eq!(session.eval("x").unwrap(), Value::Int(1));
+    assert!(matches!(session.eval("y"), Err(EvalError::Compile(_))));
+}
+
+#[test]
+fn recovers_after_a_runtime_error(){
+    let mut session = failing_engine().session();
+    session.eval("x:int = 1; create_canvas(2, 2);").unwrap();
+
+    match session.eval("y:int = 5; put(0, 0, #0000ffff); x = fail(\"stopped\");"){
+        Err(EvalError::Runtime(e)) => assert!(e.message.contains("stopped"), "{e}"),
+        other => panic!("Expected a runtime error, got {other:?}")
+    }
+
+    // What was drawn stays, what was declared doesn't
+    assert_eq!(session.canvases()[0].get(0, 0), 0x0000ffff);
+    assert!(matches!(session.eval("y"), Err(EvalError::Compile(_))));
+
+    assert_eq!(session.eval("x + 1").unwrap(), Value::Int(2));
+    session.eval("y:int = 3;").unwrap();
+    assert_eq!(session.eval("x + y").unwrap(), Value::Int(4));
+}
+
+#[test]
+fn repl_saves_the_top_canvas(){
+    let dir = std::env::temp_dir().join(format!("papyrus_repl_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("out.png");
+
+    let mut repl = Command::new(env!("CARGO_BIN_EXE_papyrus"))
+        .arg("repl")
+        .current_dir(&dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let input = format!("create_canvas(3, 2);\nfill(#102030ff);\nput(2, 1,\n    #ffffffff);\n:save {}\n:quit\n", path.display());
+    repl.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+
+    let output = repl.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let image = image::open(&path).unwrap().to_rgba8();
+    assert_eq!(image.dimensions(), (3, 2));
+    assert_eq!(image.get_pixel(0, 0).0, [0x10, 0x20, 0x30, 0xff]);
+    assert_eq!(image.get_pixel(2, 1).0, [0xff, 0xff, 0xff, 0xff]);
+}
+
+#[test]
+fn integer_overflow_is_a_runtime_error(){
+    let mut session = Engine::new().session();
+    session.eval("x:int = 2147483647;").unwrap();
+
+    match session.eval("x + 1"){
+        Err(EvalError::Runtime(e)) => assert!(e.message.contains("Integer overflow"), "{e}"),
+        other => panic!("Expected a runtime error, got {other:?}")
+    }
+    assert_eq!(session.eval("x - 1").unwrap(), Value::Int(2147483646));
+}
@@ -0,0 +1,53 @@
+use std::path::Path;
+
+use papyrus::{Engine, MemorySources, SourceProvider};
+
+const COLORS:&str = "pub def red(): color {\n    return #ff0000ff;\n}\n";
+
+#[test]
+fn compiles_scripts_held_in_memory(){
+    let sources = MemorySources::new()
+        .with("scenes/main.pprs", "import \"../shared/colors\";\nimport \"./detail\";\n\ndef main(){\n    create_canvas(2, 1);\n    put(0, 0, colors.red());\n    detail.mark();\n    save_canvas();\n}\n")
+        .with("scenes/detail.pprs", "import \"../shared/colors\";\n\npub def mark(){\n    put(1, 0, colors.red());\n}\n")
+        .with("shared/colors.pprs", COLORS);
+
+    let program = Engine::with_sources(sources).compile("scenes/main.pprs").unwrap();
+
+    // The script imported twice, through different paths, is read once
+    assert_eq!(program.scripts().len(), 3, "{:?}", program.scripts());
+
+    let saved = program.run("main", &[]).unwrap();
+    assert_eq!(saved[0].get(0, 0), 0xff0000ff);
+    assert_eq!(saved[0].get(1, 0), 0xff0000ff);
+}
+
+#[test]
+fn diagnostics_keep_the_virtual_names(){
+    let sources = MemorySources::new()
+        .with("main.pprs", "import \"lib/shapes\";\n\ndef main(){\n    shapes.square();\n}\n")
+        .with("lib/shapes.pprs", "pub def square(){\n    x:int = true;\n}\n");
+
+    let diagnostics = Engine::with_sources(sources).compile("main.pprs").unwrap_err();
+    let message = diagnostics.to_string();
+    assert!(message.contains("shapes.pprs"), "{message}");
+    assert!(diagnostics.scripts.iter().any(|e| e.ends_with("lib/shapes.pprs")), "{:?}", diagnostics.scripts);
+
+    let missing = Engine::with_sources(MemorySources::new().with("main.pprs", "import \"nowhere\";\n\ndef main(){}\n")).compile("main.pprs").unwrap_err();
+    assert!(missing.to_string().contains("nowhere"), "{missing}");
+
+    assert!(Engine::with_sources(MemorySources::new()).compile("main.pprs").is_err());
+}
+
+#[test]
+fn memory_sources_resolve_dots(){
+    let sources = MemorySources::new().with("a/../lib.pprs", COLORS).with("/root/./main.pprs", "");
+
+    assert!(sources.exists(Path::new("lib.pprs")));
+    assert!(sources.exists(Path::new("./b/../lib.pprs")));
+    assert!(sources.exists(Path::new("/root/main.pprs")));
+    assert!(sources.exists(Path::new("/../root/main.pprs")));
+    assert!(!sources.exists(Path::new("../lib.pprs")));
+
+    assert_eq!(sources.read(Path::new("lib.pprs")).unwrap(), COLORS);
+    assert!(sources.read(Path::new("other.pprs")).is_err());
+}